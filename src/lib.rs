@@ -0,0 +1,544 @@
+//! Compose column migrations in Rust instead of invoking the CLI. The CLI
+//! shares this crate's column matching and atomic file writes.
+//!
+//! ```
+//! use csv_migrator::Migrator;
+//!
+//! let output = Migrator::new(".")
+//!     .insert("status", "active", 3)
+//!     .reorder("email", 1)
+//!     .migrate_str("id,name,email\n1,Ann,ann@example.com\n")?;
+//! assert_eq!(output, "email,id,name,status\nann@example.com,1,Ann,active\n");
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use csv::StringRecord;
+use std::{
+    error::Error,
+    ffi::OsString,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// A single migration step, applied in the order it was added.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    /// Insert `column` filled with `value` at the 1-based position `order`.
+    Insert {
+        column: String,
+        value: String,
+        order: usize,
+    },
+    /// Move the `occurrence`th (1-based) column named `column` to the
+    /// 1-based position `order`.
+    Reorder {
+        column: String,
+        order: usize,
+        occurrence: usize,
+    },
+}
+
+/// Why a set of operations couldn't be applied to a file.
+#[derive(Debug)]
+pub enum MigrationError {
+    Csv(csv::Error),
+    ColumnNotFound(String),
+    AmbiguousColumn {
+        column: String,
+        first: String,
+        other: String,
+    },
+    MissingOccurrence {
+        column: String,
+        occurrences: usize,
+        occurrence: usize,
+    },
+    OrderOutOfRange {
+        order: usize,
+        columns: usize,
+    },
+    /// A data row too short to hold the column being moved.
+    ValueNotFound {
+        row: usize,
+    },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Csv(err) => write!(f, "{}", err),
+            MigrationError::ColumnNotFound(column) => write!(f, "Column {} not found", column),
+            MigrationError::AmbiguousColumn {
+                column,
+                first,
+                other,
+            } => write!(
+                f,
+                "Column {} is ambiguous between {} and {}",
+                column, first, other
+            ),
+            MigrationError::MissingOccurrence {
+                column,
+                occurrences,
+                occurrence,
+            } => write!(
+                f,
+                "Column {} occurs {} times, no occurrence {}",
+                column, occurrences, occurrence
+            ),
+            MigrationError::OrderOutOfRange { order, columns } => {
+                write!(f, "Order {} out of range for {} columns", order, columns)
+            }
+            MigrationError::ValueNotFound { row } => {
+                write!(f, "Value to migrate not found in row {}", row)
+            }
+        }
+    }
+}
+
+impl Error for MigrationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MigrationError::Csv(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<csv::Error> for MigrationError {
+    fn from(err: csv::Error) -> Self {
+        MigrationError::Csv(err)
+    }
+}
+
+/// How column names are matched against headers.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ColumnMatcher {
+    /// Match case-insensitively. Headers differing only by case make the
+    /// match ambiguous.
+    pub ignore_case: bool,
+    /// Ignore leading and trailing whitespace.
+    pub trim: bool,
+}
+
+impl ColumnMatcher {
+    pub fn matches(&self, header: &str, column: &str) -> bool {
+        let (header, column) = if self.trim {
+            (header.trim(), column.trim())
+        } else {
+            (header, column)
+        };
+        if self.ignore_case {
+            header.to_lowercase() == column.to_lowercase()
+        } else {
+            header == column
+        }
+    }
+
+    /// Index of the column named `column`, if any.
+    pub fn find_column<I>(&self, headers: I, column: &str) -> Result<Option<usize>, MigrationError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.find_occurrence(headers, column, 1)
+    }
+
+    /// Like [`ColumnMatcher::find_column`], but picks the `occurrence`th
+    /// (1-based) of several columns with the same name, failing when there
+    /// are fewer.
+    pub fn find_occurrence<I>(
+        &self,
+        headers: I,
+        column: &str,
+        occurrence: usize,
+    ) -> Result<Option<usize>, MigrationError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let matches: Vec<(usize, I::Item)> = headers
+            .into_iter()
+            .enumerate()
+            .filter(|(_, header)| self.matches(header.as_ref(), column))
+            .collect();
+        let Some((_, header)) = matches.first() else {
+            return Ok(None);
+        };
+        let header = header.as_ref();
+        if let Some((_, other)) = matches.iter().find(|(_, other)| other.as_ref() != header) {
+            return Err(MigrationError::AmbiguousColumn {
+                column: column.to_string(),
+                first: header.to_string(),
+                other: other.as_ref().to_string(),
+            });
+        }
+        match matches.get(occurrence.max(1) - 1) {
+            Some((index, _)) => Ok(Some(*index)),
+            None => Err(MigrationError::MissingOccurrence {
+                column: column.to_string(),
+                occurrences: matches.len(),
+                occurrence,
+            }),
+        }
+    }
+}
+
+enum Step {
+    Insert(usize, String),
+    Move(usize, usize),
+}
+
+/// Accumulates operations and applies them to every `.csv` file under a
+/// path, or to an in-memory string.
+#[derive(Debug, Clone)]
+pub struct Migrator {
+    path: PathBuf,
+    operations: Vec<Operation>,
+    matcher: ColumnMatcher,
+}
+
+impl Migrator {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            operations: vec![],
+            matcher: ColumnMatcher::default(),
+        }
+    }
+
+    /// Match the columns of later `reorder` calls as `matcher` does.
+    ///
+    /// ```
+    /// use csv_migrator::{ColumnMatcher, Migrator};
+    ///
+    /// let output = Migrator::new(".")
+    ///     .matching(ColumnMatcher { ignore_case: true, trim: false })
+    ///     .reorder("EMAIL", 1)
+    ///     .migrate_str("name,email\nAnn,ann@example.com\n")?;
+    /// assert_eq!(output, "email,name\nann@example.com,Ann\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn matching(mut self, matcher: ColumnMatcher) -> Self {
+        self.matcher = matcher;
+        self
+    }
+
+    pub fn insert(mut self, column: &str, value: &str, order: usize) -> Self {
+        self.operations.push(Operation::Insert {
+            column: column.to_string(),
+            value: value.to_string(),
+            order,
+        });
+        self
+    }
+
+    pub fn reorder(self, column: &str, order: usize) -> Self {
+        self.reorder_occurrence(column, 1, order)
+    }
+
+    /// Move the `occurrence`th (1-based) of several columns named `column`.
+    pub fn reorder_occurrence(mut self, column: &str, occurrence: usize, order: usize) -> Self {
+        self.operations.push(Operation::Reorder {
+            column: column.to_string(),
+            order,
+            occurrence,
+        });
+        self
+    }
+
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Migrate CSV content without touching the file system. The path given
+    /// to [`Migrator::new`] is not used.
+    pub fn migrate_str(&self, input: &str) -> Result<String, Box<dyn Error>> {
+        let mut reader = csv::Reader::from_reader(input.as_bytes());
+        let mut writer = csv::Writer::from_writer(vec![]);
+        let (headers, _) = plan(reader.headers()?, &self.operations, &self.matcher)?;
+        writer.write_record(&headers)?;
+        for record in records(&mut reader, &self.operations, &self.matcher) {
+            writer.write_record(&record?)?;
+        }
+        Ok(String::from_utf8(writer.into_inner()?)?)
+    }
+
+    /// Migrate every `.csv` file under the path in place, returning the
+    /// number of files changed.
+    ///
+    /// ```
+    /// use csv_migrator::Migrator;
+    /// use std::fs;
+    ///
+    /// let dir = std::env::temp_dir().join("csv_migrator_doctest_run");
+    /// fs::create_dir_all(&dir)?;
+    /// fs::write(dir.join("users.csv"), "name,email\nAnn,ann@example.com\n")?;
+    ///
+    /// let changed = Migrator::new(&dir).reorder("email", 1).run()?;
+    /// assert_eq!(changed, 1);
+    /// assert_eq!(
+    ///     fs::read_to_string(dir.join("users.csv"))?,
+    ///     "email,name\nann@example.com,Ann\n"
+    /// );
+    /// # fs::remove_dir_all(&dir)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn run(&self) -> Result<usize, Box<dyn Error>> {
+        let mut changed = 0;
+        for file in csv_files(&self.path)? {
+            let content = fs::read_to_string(&file)?;
+            let output = self
+                .migrate_str(&content)
+                .map_err(|e| format!("{}: {}", file.display(), e))?;
+            if output != content {
+                write_atomic(&file, output)?;
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Async variant of [`Migrator::run`] for embedders doing IO-bound work
+    /// on a tokio runtime. Files are read with `tokio::fs`, while parsing and
+    /// the atomic write run on the blocking pool via `spawn_blocking`, so a
+    /// large file doesn't stall the runtime's worker threads.
+    ///
+    /// ```no_run
+    /// use csv_migrator::Migrator;
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let changed = Migrator::new("uploads").reorder("email", 1).run_async().await?;
+    /// println!("{} files changed", changed);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn run_async(&self) -> Result<usize, Box<dyn Error>> {
+        let migrator = std::sync::Arc::new(self.clone());
+        let mut changed = 0;
+        for file in csv_files_async(&self.path).await? {
+            let content = tokio::fs::read_to_string(&file).await?;
+            let migrator = migrator.clone();
+            let path = file.clone();
+            let written = tokio::task::spawn_blocking(move || {
+                let output = migrator.migrate_str(&content).map_err(|e| e.to_string())?;
+                if output == content {
+                    return Ok(false);
+                }
+                write_atomic(&path, output).map_err(|e| e.to_string())?;
+                Ok::<_, String>(true)
+            })
+            .await?
+            .map_err(|e| format!("{}: {}", file.display(), e))?;
+            if written {
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"))
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// Like the CLI without --hidden, files and directories starting with a dot
+/// are left out.
+fn csv_files(path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = vec![];
+    let walker = WalkDir::new(path).sort_by_file_name().into_iter();
+    for entry in walker.filter_entry(|entry| entry.depth() == 0 || !is_hidden(entry.path())) {
+        let entry = entry?;
+        if entry.file_type().is_file() && is_csv(entry.path()) {
+            files.push(entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(feature = "tokio")]
+async fn csv_files_async(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if tokio::fs::metadata(path).await?.is_file() {
+        let files = if is_csv(path) {
+            vec![path.to_path_buf()]
+        } else {
+            vec![]
+        };
+        return Ok(files);
+    }
+    let mut files = vec![];
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if is_hidden(&entry.path()) {
+                continue;
+            }
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() && is_csv(&entry.path()) {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The headers a reader's records have after applying `operations`.
+pub fn migrated_headers(
+    headers: &StringRecord,
+    operations: &[Operation],
+) -> Result<StringRecord, MigrationError> {
+    let (headers, _) = plan(headers, operations, &ColumnMatcher::default())?;
+    Ok(StringRecord::from(headers))
+}
+
+/// Lazily apply `operations` to each record of `reader`, for consumers that
+/// stream the migrated records elsewhere instead of writing a file.
+///
+/// ```
+/// use csv_migrator::{migrated_records, Operation};
+///
+/// let mut reader = csv::Reader::from_reader("name,email\nAnn,ann@example.com\n".as_bytes());
+/// let operations = [Operation::Reorder {
+///     column: "email".to_string(),
+///     order: 1,
+///     occurrence: 1,
+/// }];
+/// for record in migrated_records(&mut reader, &operations) {
+///     assert_eq!(&record?, vec!["ann@example.com", "Ann"]);
+/// }
+/// # Ok::<(), csv_migrator::MigrationError>(())
+/// ```
+pub fn migrated_records<'a, R: io::Read>(
+    reader: &'a mut csv::Reader<R>,
+    operations: &'a [Operation],
+) -> impl Iterator<Item = Result<StringRecord, MigrationError>> + 'a {
+    records(reader, operations, &ColumnMatcher::default())
+}
+
+fn records<'a, R: io::Read>(
+    reader: &'a mut csv::Reader<R>,
+    operations: &'a [Operation],
+    matcher: &ColumnMatcher,
+) -> impl Iterator<Item = Result<StringRecord, MigrationError>> + 'a {
+    let planned = reader
+        .headers()
+        .map_err(MigrationError::from)
+        .and_then(|headers| plan(headers, operations, matcher));
+    let (steps, failed) = match planned {
+        Ok((_, steps)) => (steps, None),
+        Err(err) => (vec![], Some(err)),
+    };
+    let records = failed.is_none().then(|| reader.records());
+    failed
+        .map(Err)
+        .into_iter()
+        .chain(
+            records
+                .into_iter()
+                .flatten()
+                .enumerate()
+                .map(move |(i, record)| {
+                    let record = apply(&record?, &steps)
+                        .ok_or(MigrationError::ValueNotFound { row: i + 1 })?;
+                    Ok(StringRecord::from(record))
+                }),
+        )
+}
+
+fn plan(
+    headers: &StringRecord,
+    operations: &[Operation],
+    matcher: &ColumnMatcher,
+) -> Result<(Vec<String>, Vec<Step>), MigrationError> {
+    let mut headers: Vec<String> = headers.iter().map(String::from).collect();
+    let mut steps = vec![];
+    for operation in operations {
+        match operation {
+            Operation::Insert {
+                column,
+                value,
+                order,
+            } => {
+                if *order < 1 || *order > headers.len() + 1 {
+                    return Err(MigrationError::OrderOutOfRange {
+                        order: *order,
+                        columns: headers.len(),
+                    });
+                }
+                headers.insert(order - 1, column.clone());
+                steps.push(Step::Insert(order - 1, value.clone()));
+            }
+            Operation::Reorder {
+                column,
+                order,
+                occurrence,
+            } => {
+                let from = matcher
+                    .find_occurrence(&headers, column, *occurrence)?
+                    .ok_or_else(|| MigrationError::ColumnNotFound(column.clone()))?;
+                if *order < 1 || *order > headers.len() {
+                    return Err(MigrationError::OrderOutOfRange {
+                        order: *order,
+                        columns: headers.len(),
+                    });
+                }
+                let header = headers.remove(from);
+                headers.insert(order - 1, header);
+                steps.push(Step::Move(from, order - 1));
+            }
+        }
+    }
+    Ok((headers, steps))
+}
+
+/// The migrated record, or `None` when it is too short for a move, as
+/// moving a value that isn't there would shift the rest out of line.
+fn apply(record: &StringRecord, steps: &[Step]) -> Option<Vec<String>> {
+    let mut record: Vec<String> = record.iter().map(String::from).collect();
+    for step in steps {
+        match step {
+            Step::Insert(index, value) => record.insert((*index).min(record.len()), value.clone()),
+            Step::Move(from, _) if *from >= record.len() => return None,
+            Step::Move(from, to) => {
+                let value = record.remove(*from);
+                record.insert((*to).min(record.len()), value);
+            }
+        }
+    }
+    Some(record)
+}
+
+/// Write through a temporary sibling and rename it over `path`, so an
+/// interrupted run never leaves a half-written file behind.
+pub fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref();
+    // replace the target of a symlink, not the link itself
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut temp_name = OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(".csv-migrator.tmp");
+    let temp = path.with_file_name(temp_name);
+
+    fs::write(&temp, contents)?;
+    let renamed = match fs::metadata(&path) {
+        Ok(metadata) => fs::set_permissions(&temp, metadata.permissions()),
+        Err(_) => Ok(()),
+    }
+    .and_then(|_| fs::rename(&temp, &path));
+    if renamed.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    renamed
+}
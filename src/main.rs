@@ -1,33 +1,627 @@
-use clap::{arg, command, Args, Parser, Subcommand};
+use chrono::{Local, SecondsFormat};
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand, ValueEnum};
 use colored::Colorize;
 use csv::StringRecord;
+use csv_migrator::{write_atomic, ColumnMatcher};
+use indexmap::{IndexMap, IndexSet};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::{HashMap, HashSet, VecDeque},
+    env,
     error::Error,
-    fs::{self, File},
-    io::Read,
-    path::PathBuf,
+    ffi::OsStr,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, IsTerminal, Write},
+    path::{Component, Path, PathBuf},
+    process::{self, Stdio},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
 };
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use uuid::Uuid;
+use walkdir::WalkDir;
 
 trait Migration {
     type ConfigType;
     fn new(config: Self::ConfigType) -> Self;
     fn run(&self) -> Result<(), Box<dyn Error>>;
-    fn get_csv_files(&self, path: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
-        let mut csv_file_paths: Vec<PathBuf> = vec![];
-        let entries = fs::read_dir(path)?;
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
+    fn get_csv_files(
+        &self,
+        path: &str,
+        common: &CommonConfig,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        self.iter_csv_files(path, common).collect()
+    }
+    fn iter_csv_files(
+        &self,
+        path: &str,
+        common: &CommonConfig,
+    ) -> impl Iterator<Item = Result<PathBuf, Box<dyn Error>>> {
+        let mut walker = WalkDir::new(path)
+            .follow_links(common.follow_symlinks)
+            .sort_by_file_name();
+        if let Some(max_depth) = common.max_depth {
+            walker = walker.max_depth(max_depth);
+        }
+        let mut visited = HashSet::new();
+        let hidden = common.hidden;
+        let extensions = common.extensions.clone();
+        let entries = walker.into_iter().filter_entry(move |entry| {
+            if entry.depth() > 0 && !hidden && entry.file_name().to_string_lossy().starts_with('.')
+            {
+                return false;
+            }
+            // a directory reached twice through symlinks would loop forever
+            if entry.file_type().is_dir() {
+                return fs::canonicalize(entry.path()).is_ok_and(|p| visited.insert(p));
+            }
+            true
+        });
+
+        entries.filter_map(move |entry| {
+            let path = match entry {
+                Err(err) if err.loop_ancestor().is_some() => return None,
+                Err(err) => return Some(Err(err.into())),
+                Ok(entry) => entry.into_path(),
+            };
             if path.is_dir() {
-                let mut traversal_res = self.get_csv_files(path.to_str().unwrap())?;
-                csv_file_paths.append(&mut traversal_res);
+                return None;
             }
             let extension = path.extension().unwrap_or_default();
-            if extension.to_ascii_lowercase() == "csv" {
-                csv_file_paths.push(path);
+            extensions
+                .iter()
+                .any(|e| extension.eq_ignore_ascii_case(e.trim_start_matches('.')))
+                .then_some(Ok(path))
+        })
+    }
+    fn write_if_changed(
+        &self,
+        path: &PathBuf,
+        original: &str,
+        output: Vec<u8>,
+    ) -> Result<bool, Box<dyn Error>> {
+        if output == original.as_bytes() {
+            return Ok(false);
+        }
+        write_atomic(path, output)?;
+        Ok(true)
+    }
+    fn verify_written(
+        &self,
+        path: &PathBuf,
+        raw: &[u8],
+        original: &str,
+        common: &CommonConfig,
+        expected_header: (usize, &str),
+        check_row: impl Fn(&StringRecord, &StringRecord) -> bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let written = fs::read_to_string(path)?;
+        let (_, original_data) = common.split_preamble(original);
+        let (_, written_data) = common.split_preamble(&written);
+        // ragged rows are expected after --only-header
+        let mut builder = common.reader_builder(path);
+        builder.flexible(true);
+        let mut original_reader = builder.from_reader(original_data.as_bytes());
+        let mut written_reader = builder.from_reader(written_data.as_bytes());
+
+        let (index, column) = expected_header;
+        let mut failure = match written_reader.headers()?.get(index) {
+            Some(header) if common.matching.matches(header, column) => None,
+            _ => Some(format!("column {} is not on #{}", column, index + 1)),
+        };
+        let original_records = original_reader.records().take(common.sample_size());
+        let mut written_records = written_reader.records();
+        for (i, original_record) in original_records.enumerate() {
+            if failure.is_some() {
+                break;
+            }
+            let original_record = original_record?;
+            failure = match written_records.next().transpose()? {
+                Some(written_record) if check_row(&original_record, &written_record) => None,
+                Some(_) => Some(format!("row {} doesn't match its original", i + 1)),
+                None => Some(format!("row {} is missing", i + 1)),
+            };
+        }
+        if failure.is_none() && written_records.next().is_some() {
+            failure = Some("rows were added".to_string());
+        }
+
+        match failure {
+            None => Ok(()),
+            Some(reason) => {
+                write_atomic(path, raw)?;
+                Err(format!("Verification failed, original restored: {}", reason).into())
+            }
+        }
+    }
+    fn write_unless_dry_run(
+        &self,
+        path: &PathBuf,
+        original: &str,
+        output: Vec<u8>,
+        dry_run: bool,
+    ) -> Result<FileOutcome, Box<dyn Error>> {
+        if dry_run {
+            return Ok(FileOutcome::preview(output != original.as_bytes()));
+        }
+        Ok(self.write_if_changed(path, original, output)?.into())
+    }
+    fn output_path(
+        &self,
+        root: &str,
+        file: &Path,
+        output_dir: &Option<String>,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let Some(output_dir) = output_dir else {
+            return Ok(file.to_path_buf());
+        };
+        // a --path naming a single file keeps just its file name
+        let relative = match file.strip_prefix(root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => Path::new(file.file_name().unwrap_or_default()),
+        };
+        // only plain names may be joined, so writes stay under output_dir
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(format!(
+                "Refusing to write {:?} outside output directory {}",
+                relative, output_dir
+            )
+            .into());
+        }
+        Ok(Path::new(output_dir).join(relative))
+    }
+    fn destination(
+        &self,
+        root: &str,
+        file: &Path,
+        output: &OutputConfig,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let Some(suffix) = &output.output_suffix else {
+            let destination = self.output_path(root, file, &output.output_dir)?;
+            return Ok(match output.renamed(file)? {
+                Some(name) => destination.with_file_name(name),
+                None => destination,
+            });
+        };
+        let mut name = file.file_stem().unwrap_or_default().to_os_string();
+        name.push(suffix);
+        if let Some(extension) = file.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        let destination = file.with_file_name(name);
+        if destination.exists() && !output.force {
+            return Err(format!(
+                "{:?} already exists, pass --force to overwrite it",
+                destination
+            )
+            .into());
+        }
+        Ok(destination)
+    }
+    fn check_renames(
+        &self,
+        root: &str,
+        files: &[PathBuf],
+        output: &OutputConfig,
+    ) -> Result<(), Box<dyn Error>> {
+        if output.rename_to.is_none() {
+            return Ok(());
+        }
+        let mut renamed: HashMap<PathBuf, &PathBuf> = HashMap::new();
+        for file in files {
+            let destination = self.destination(root, file, output)?;
+            if let Some(other) = renamed.insert(destination.clone(), file) {
+                return Err(format!(
+                    "{:?} and {:?} would both be renamed to {:?}",
+                    other, file, destination
+                )
+                .into());
+            }
+            if destination != *file && destination.exists() {
+                return Err(
+                    format!("Renaming {:?} would overwrite {:?}", file, destination).into(),
+                );
+            }
+        }
+        Ok(())
+    }
+    fn write_to(
+        &self,
+        root: &str,
+        path: &PathBuf,
+        output: &OutputConfig,
+        original: &str,
+        bytes: Vec<u8>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let destination = self.destination(root, path, output)?;
+        let changed = self.write_output(path, &destination, original, bytes)?;
+        // renaming in place moves the original instead of copying it
+        if output.output_dir.is_none() && output.rename_to.is_some() && destination != *path {
+            fs::remove_file(path)?;
+            return Ok(true);
+        }
+        Ok(changed)
+    }
+    fn write_output(
+        &self,
+        path: &PathBuf,
+        destination: &Path,
+        original: &str,
+        output: Vec<u8>,
+    ) -> Result<bool, Box<dyn Error>> {
+        if destination == path {
+            return self.write_if_changed(path, original, output);
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(destination, output)?;
+        Ok(true)
+    }
+    fn report_column_diff(&self, before: &StringRecord, after: &[&str]) -> bool {
+        let mut unmatched: Vec<Option<&str>> = before.iter().map(Some).collect();
+        let mut kept = vec![];
+        for (new_index, header) in after.iter().enumerate() {
+            let old_index = unmatched.iter().position(|h| h == &Some(*header));
+            match old_index {
+                Some(old_index) => {
+                    unmatched[old_index] = None;
+                    kept.push((old_index, new_index));
+                }
+                None => println!("+ {} #{}", header, new_index + 1),
+            }
+        }
+        for (old_index, header) in unmatched.iter().enumerate() {
+            if let Some(header) = header {
+                println!("- {} #{}", header, old_index + 1);
+            }
+        }
+
+        // columns outside the longest run keeping their relative order are
+        // the ones that moved, rather than shifted by an insert or removal
+        let mut run_lengths: Vec<usize> = vec![1; kept.len()];
+        for i in 0..kept.len() {
+            for j in 0..i {
+                if kept[j].0 < kept[i].0 {
+                    run_lengths[i] = run_lengths[i].max(run_lengths[j] + 1);
+                }
+            }
+        }
+        let mut in_run = vec![false; kept.len()];
+        let mut length = run_lengths.iter().copied().max().unwrap_or_default();
+        let mut next_old_index = usize::MAX;
+        for i in (0..kept.len()).rev() {
+            if length > 0 && run_lengths[i] == length && kept[i].0 < next_old_index {
+                in_run[i] = true;
+                next_old_index = kept[i].0;
+                length -= 1;
+            }
+        }
+        for (&(old_index, new_index), in_run) in kept.iter().zip(in_run) {
+            if !in_run {
+                println!(
+                    "~ {} #{} -> #{}",
+                    after[new_index],
+                    old_index + 1,
+                    new_index + 1
+                );
+            }
+        }
+        before.iter().ne(after.iter().copied())
+    }
+    fn copy_data_rows(
+        &self,
+        content: &str,
+        reader: &csv::Reader<&[u8]>,
+        writer: csv::Writer<Vec<u8>>,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut output = writer.into_inner()?;
+        let data_start = reader.position().byte() as usize;
+        let header_line = &content[..data_start];
+        let data = &content[data_start..];
+        // the header position sits between the \r and \n of a CRLF terminator
+        let (terminator, data) = if header_line.ends_with('\r') {
+            match data.strip_prefix('\n') {
+                Some(data) => ("\r\n", data),
+                None => ("\r", data),
+            }
+        } else if header_line.ends_with("\r\n") {
+            ("\r\n", data)
+        } else {
+            ("\n", data)
+        };
+        // end the rewritten header the way the original header line ended
+        if output.last() == Some(&b'\n') {
+            output.pop();
+            output.extend_from_slice(terminator.as_bytes());
+        }
+        output.extend_from_slice(data.as_bytes());
+        Ok(output)
+    }
+    fn migrate_files<O: Into<FileOutcome>>(
+        &self,
+        files: &[PathBuf],
+        common: &CommonConfig,
+        mut migrate_file: impl FnMut(&PathBuf) -> Result<O, Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut changed = 0;
+        let mut previewed = 0;
+        let mut unchanged = 0;
+        let mut skipped = 0;
+        let every_file = common.progress_every == 1;
+        let done = common.checkpointed()?;
+        let mut checkpoint = match &common.checkpoint {
+            Some(checkpoint) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(checkpoint)
+                    .with_path(checkpoint)?,
+            ),
+            None => None,
+        };
+        let show_bytes = common.progress_bytes && io::stdout().is_terminal();
+        let total_bytes: u64 = if show_bytes {
+            files
+                .iter()
+                .filter(|file| !done.contains(*file))
+                .filter_map(|file| fs::metadata(file).ok())
+                .map(|metadata| metadata.len())
+                .sum()
+        } else {
+            0
+        };
+        let started = Instant::now();
+        let mut read_bytes = 0;
+        let mut resumed = 0;
+        for (i, file) in files.iter().enumerate() {
+            if done.contains(file) {
+                resumed += 1;
+                continue;
+            }
+            if every_file {
+                println!("Migrating {:?}", &file);
+            } else if (i as u64).is_multiple_of(common.progress_every) {
+                println!("Migrating file {} of {}", i + 1, files.len());
+            }
+            let size = fs::metadata(file).with_path(file)?.len();
+            let outcome = if size == 0 {
+                FileOutcome::Skipped("file is empty".to_string())
+            } else if common.max_file_size.is_some_and(|max| size > max) {
+                FileOutcome::Skipped(format!("{} bytes is over --max-file-size", size))
+            } else if !common.header_expected(file).with_path(file)? {
+                FileOutcome::Skipped("headers don't match --only-if-header".to_string())
+            } else {
+                self.with_retries(file, common, every_file, &mut migrate_file)?
+                    .into()
+            };
+            read_bytes += size;
+            if show_bytes {
+                println!(
+                    "{}",
+                    progress_bytes(read_bytes, total_bytes, started.elapsed())
+                );
+            }
+            // only files written or found already migrated are done; skipped
+            // and previewed ones are left for a resumed run
+            if matches!(outcome, FileOutcome::Changed | FileOutcome::Unchanged) {
+                if let Some(checkpoint) = &mut checkpoint {
+                    writeln!(checkpoint, "{}", file.display())?;
+                }
+            }
+            match outcome {
+                FileOutcome::Changed => changed += 1,
+                FileOutcome::Previewed if every_file => {
+                    println!("{}", "Would change".yellow());
+                    previewed += 1;
+                }
+                FileOutcome::Previewed => previewed += 1,
+                FileOutcome::Ignored => {}
+                FileOutcome::Unchanged if every_file => {
+                    println!("{}", "Unchanged".yellow());
+                    unchanged += 1;
+                }
+                FileOutcome::Unchanged => unchanged += 1,
+                FileOutcome::Skipped(reason) if every_file => {
+                    warn(format!("Skipped, {}", reason));
+                    skipped += 1;
+                }
+                FileOutcome::Skipped(reason) => {
+                    warn(format!("Skipped {:?}, {}", file, reason));
+                    skipped += 1;
+                }
+            }
+        }
+        for (tally, count) in [
+            (&MIGRATED, changed),
+            (&PREVIEWED, previewed),
+            (&UNCHANGED, unchanged),
+            (&SKIPPED, skipped),
+        ] {
+            tally.with(|tally| tally.set(tally.get() + count));
+        }
+        if resumed > 0 {
+            println!("{} files already done per checkpoint", resumed);
+        }
+        println!("{} of {} files changed", changed, files.len());
+        if previewed > 0 {
+            println!("{} files would change", previewed);
+        }
+        if skipped > 0 {
+            println!("{}", format!("{} files skipped", skipped).yellow());
+            if common.strict {
+                return Err(format!("{} files skipped in strict mode", skipped).into());
+            }
+        }
+        let warnings = WARNINGS.with(Cell::get);
+        if warnings > 0 && common.strict {
+            return Err(format!("{} warnings in strict mode", warnings).into());
+        }
+        Ok(())
+    }
+    /// With --precheck, report the files where `column` is missing (or, when
+    /// `present` is false, already there) before any file is written.
+    fn precheck(
+        &self,
+        files: &[PathBuf],
+        common: &CommonConfig,
+        column: &str,
+        present: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        if !common.precheck {
+            return Ok(());
+        }
+        let mut failed = 0;
+        for file in files {
+            let content = common.read(file).with_path(file)?;
+            let (_, data) = common.split_preamble(&content);
+            let headers = common.reader(file, data).headers()?.clone();
+            if common.matching.find_column(&headers, column)?.is_some() != present {
+                let problem = if present { "lacks" } else { "already has" };
+                warn(format!("{:?} {} column {}", file, problem, column));
+                failed += 1;
+            }
+        }
+        println!(
+            "Precheck: {} of {} files ok",
+            files.len() - failed,
+            files.len()
+        );
+        if failed > 0 && common.strict {
+            return Err(
+                format!("{} files failed the precheck, nothing was written", failed).into(),
+            );
+        }
+        Ok(())
+    }
+    fn with_retries<O>(
+        &self,
+        file: &PathBuf,
+        common: &CommonConfig,
+        verbose: bool,
+        migrate_file: &mut impl FnMut(&PathBuf) -> Result<O, Box<dyn Error>>,
+    ) -> Result<O, Box<dyn Error>> {
+        let mut retries = 0;
+        loop {
+            match migrate_file(file) {
+                Err(err) if retries < common.retries && is_transient(err.as_ref()) => {
+                    let delay = common.retry_delay.saturating_mul(1 << retries.min(16));
+                    retries += 1;
+                    if verbose {
+                        println!(
+                            "{}",
+                            format!(
+                                "Retry {} of {} in {}ms: {}",
+                                retries, common.retries, delay, err
+                            )
+                            .yellow()
+                        );
+                    }
+                    thread::sleep(Duration::from_millis(delay));
+                }
+                result => return result.with_path(file),
+            }
+        }
+    }
+    fn migrate_cells(
+        &self,
+        files: &[PathBuf],
+        common: &CommonConfig,
+        action: &str,
+        dry_run: bool,
+        mut migrate_file: impl FnMut(&PathBuf) -> Result<(FileOutcome, usize), Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let tally = |cells: usize| {
+            if dry_run {
+                format!("{} cells would be {}", cells, action)
+            } else {
+                format!("{} cells {}", cells, action)
+            }
+        };
+        let mut total = 0;
+        self.migrate_files(files, common, |file| {
+            let (outcome, cells) = migrate_file(file)?;
+            if cells > 0 {
+                println!("{}", tally(cells));
             }
+            total += cells;
+            Ok(outcome)
+        })?;
+        println!("{} in total", tally(total));
+        Ok(())
+    }
+}
+
+enum FileOutcome {
+    Changed,
+    /// Would change, but --dry-run or --diff-columns left the file alone
+    Previewed,
+    Unchanged,
+    Skipped(String),
+    Ignored,
+}
+
+impl FileOutcome {
+    fn preview(would_change: bool) -> Self {
+        if would_change {
+            FileOutcome::Previewed
+        } else {
+            FileOutcome::Unchanged
+        }
+    }
+}
+
+impl From<bool> for FileOutcome {
+    fn from(changed: bool) -> Self {
+        if changed {
+            FileOutcome::Changed
+        } else {
+            FileOutcome::Unchanged
         }
-        Ok(csv_file_paths)
+    }
+}
+
+#[derive(Debug)]
+struct FileError {
+    path: PathBuf,
+    source: Box<dyn Error>,
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.source)
+    }
+}
+
+impl Error for FileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+trait WithPath<T> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T, Box<dyn Error>>;
+}
+
+impl<T, E: Into<Box<dyn Error>>> WithPath<T> for Result<T, E> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T, Box<dyn Error>> {
+        self.map_err(|source| {
+            FileError {
+                path: path.as_ref().to_path_buf(),
+                source: source.into(),
+            }
+            .into()
+        })
     }
 }
 
@@ -41,18 +635,116 @@ struct Cli {
 enum Commands {
     Insert(InsertConfig),
     Reorder(ReorderConfig),
+    Join(JoinConfig),
+    Diff(DiffConfig),
+    Flatten(FlattenConfig),
+    InsertRow(InsertRowConfig),
+    Head(HeadConfig),
+    Tail(TailConfig),
+    Manifest(ManifestConfig),
+    Distinct(DistinctConfig),
+    Coalesce(CoalesceConfig),
+    Pad(PadConfig),
+    Slice(SliceConfig),
+    Pipe(PipeConfig),
+    FromJson(FromJsonConfig),
+    ToFixed(ToFixedConfig),
+    Transpose(TransposeConfig),
+    Melt(MeltConfig),
+    Pivot(PivotConfig),
+    ReplaceHeaders(ReplaceHeadersConfig),
+    DedupeColumns(DedupeColumnsConfig),
+    NormalizeHeaders(NormalizeConfig),
+    Assert(AssertConfig),
+    Clamp(ClampConfig),
+    #[command(hide = true)]
+    SelfCheck(SelfCheckConfig),
+    Range(RangeConfig),
+    Anonymize(AnonConfig),
+    Deaccent(DeaccentConfig),
+    RenameMany(RenameManyConfig),
+    Round(RoundConfig),
+    NameColumns(NameColumnsConfig),
+    Unreorder(UnreorderConfig),
+    SplitBytes(SplitBytesConfig),
+    TrimColumns(TrimColumnsConfig),
+    FillFrom(FillFromConfig),
+    HeaderInventory(HeaderInventoryConfig),
 }
 
 #[derive(Args, Debug, Clone)]
+#[command(group(
+    ArgGroup::new("value")
+        .required(true)
+        .args(["default_value", "template", "default_env", "default_builtin"])
+))]
 struct InsertConfig {
     #[arg(long)]
     path: String,
     #[arg(long)]
     column: String,
     #[arg(long)]
-    default_value: String,
+    default_value: Option<String>,
+    /// Build the value from other columns of the row, e.g. "{first}-{last}"
     #[arg(long)]
-    order: i32,
+    template: Option<String>,
+    /// Use the value of an environment variable
+    #[arg(long)]
+    default_env: Option<String>,
+    /// `now` and `date` are captured once per run, `uuid` is generated per row
+    #[arg(long, value_enum)]
+    default_builtin: Option<Builtin>,
+    /// 1-based position, or `last` to append after the last column of each file
+    #[arg(long)]
+    order: InsertOrder,
+    /// Only add the header, copying data rows verbatim. Data rows are left
+    /// one field short, so the file becomes ragged until they are fixed
+    #[arg(long)]
+    only_header: bool,
+    /// Re-read each written file and restore it if the column or rows look wrong
+    #[arg(long)]
+    verify_after: bool,
+    /// Print the columns each file would gain or move instead of writing
+    #[arg(long, conflicts_with = "verify_after")]
+    diff_columns: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InsertOrder {
+    Position(i32),
+    Last,
+}
+
+impl FromStr for InsertOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last" => Ok(InsertOrder::Last),
+            _ => s
+                .parse()
+                .map(InsertOrder::Position)
+                .map_err(|_| format!("expected a position or `last`, got {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for InsertOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertOrder::Position(order) => write!(f, "{}", order),
+            InsertOrder::Last => write!(f, "last"),
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum Builtin {
+    Now,
+    Date,
+    Uuid,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -61,274 +753,8202 @@ struct ReorderConfig {
     path: String,
     #[arg(long)]
     column: String,
+    /// Which of several columns named `--column` to move, 1-based
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    occurrence: u64,
     #[arg(long)]
     order: i32,
+    /// Only move the header, copying data rows verbatim
+    #[arg(long)]
+    only_header: bool,
+    /// Re-read each written file and restore it if the column or rows look wrong
+    #[arg(long)]
+    verify_after: bool,
+    /// Print the columns each file would gain or move instead of writing
+    #[arg(long, conflicts_with = "verify_after")]
+    diff_columns: bool,
+    /// Append each file's header order before and after the move to this file, for Unreorder
+    #[arg(long)]
+    record_permutation: Option<String>,
+    #[command(flatten)]
+    common: CommonConfig,
 }
 
-fn main() {
-    let cli = Cli::parse();
-    run(cli).unwrap_or_else(|_| println!("{}", "Migration failed".red()));
-    println!("{}", "Migration done".green());
+/// Restore header orders recorded by `Reorder --record-permutation`
+#[derive(Args, Debug, Clone)]
+struct UnreorderConfig {
+    #[arg(long)]
+    path: String,
+    /// File written by --record-permutation
+    #[arg(long)]
+    permutations: String,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Replace line breaks inside field values, in every column or only `--column`
+#[derive(Args, Debug, Clone)]
+struct FlattenConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long)]
+    column: Option<String>,
+    #[arg(long, default_value = " ")]
+    replacement: String,
+    /// Report how many cells would change without writing
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Insert a constant row, e.g. a units row, among the data rows
+#[derive(Args, Debug, Clone)]
+struct InsertRowConfig {
+    #[arg(long)]
+    path: String,
+    /// One value per column, comma-separated with CSV quoting
+    #[arg(long)]
+    values: String,
+    /// 1-based data row position, 1 puts the row right after the header
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    at: u64,
+    #[command(flatten)]
+    common: CommonConfig,
 }
 
-fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
-    match cli.command {
-        Commands::Insert(insert_config) => InsertMigration::new(insert_config).run().unwrap(),
-        Commands::Reorder(reorder_config) => ReorderMigration::new(reorder_config).run().unwrap(),
-    };
+/// Keep only the first N data rows of each file
+#[derive(Args, Debug, Clone)]
+struct HeadConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long)]
+    rows: usize,
+    #[command(flatten)]
+    output: OutputConfig,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Keep only the last N data rows of each file. The file is read whole, but
+/// at most N parsed rows are kept while scanning it
+#[derive(Args, Debug, Clone)]
+struct TailConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long)]
+    rows: usize,
+    #[command(flatten)]
+    output: OutputConfig,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Write the SHA-256 hash and row count of every file to a JSON manifest.
+/// Diffing manifests taken before and after a migration shows what changed
+#[derive(Args, Debug, Clone)]
+struct ManifestConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long)]
+    output: String,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Count the distinct values of a column across all files
+#[derive(Args, Debug, Clone)]
+struct DistinctConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long, required_unless_present = "dump_distinct")]
+    column: Option<String>,
+    /// How many of the values to print, 20 by default, or to write per
+    /// column, all by default
+    #[arg(long)]
+    top: Option<usize>,
+    /// Write the values and counts of every column, or just --column, to
+    /// one CSV per column in this directory
+    #[arg(long)]
+    dump_distinct: Option<String>,
+    #[arg(long, value_enum, default_value_t = DistinctSort::Count)]
+    sort: DistinctSort,
+    /// List values in the order they are first seen, files in name order, instead of sorting
+    #[arg(long, conflicts_with = "sort")]
+    preserve_order: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum DistinctSort {
+    Count,
+    Value,
+}
+
+/// Insert a column holding the first non-empty value of the source columns
+#[derive(Args, Debug, Clone)]
+struct CoalesceConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long)]
+    into: String,
+    /// Source columns in order of preference, comma-separated
+    #[arg(long, value_delimiter = ',', required_unless_present = "columns_file")]
+    from: Vec<String>,
+    /// File with more columns, one per line, ignoring blank lines and `#` comments
+    #[arg(long)]
+    columns_file: Option<String>,
+    /// 1-based position, or `last` to append after the last column of each file
+    #[arg(long)]
+    order: InsertOrder,
+    /// Keep the source columns next to the new one
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    keep_sources: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Pad the values of a column to a fixed width, e.g. ZIP codes to 00123
+#[derive(Args, Debug, Clone)]
+struct PadConfig {
+    #[arg(long)]
+    path: String,
+    #[command(flatten)]
+    column: ColumnSelector,
+    /// Only change rows where this holds, e.g. `type==special`
+    #[arg(long = "where")]
+    row_filter: Option<RowFilter>,
+    /// Target width in characters, longer values are left unchanged
+    #[arg(long)]
+    width: usize,
+    #[arg(long, default_value_t = '0')]
+    char: char,
+    #[arg(long, value_enum, default_value_t = PadSide::Left)]
+    side: PadSide,
+    /// Report how many cells would change without writing
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum PadSide {
+    Left,
+    Right,
+}
+
+/// Replace the values of a column with a substring. Offsets count
+/// characters, not bytes, and are clamped to the length of each value.
+#[derive(Args, Debug, Clone)]
+struct SliceConfig {
+    #[arg(long)]
+    path: String,
+    #[command(flatten)]
+    column: ColumnSelector,
+    /// Only change rows where this holds, e.g. `type==special`
+    #[arg(long = "where")]
+    row_filter: Option<RowFilter>,
+    /// 0-based character offset of the first kept character
+    #[arg(long, default_value_t = 0)]
+    start: usize,
+    /// 0-based character offset after the last kept character, defaults to the end
+    #[arg(long)]
+    end: Option<usize>,
+    /// Report how many cells would change without writing
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Replace the values of a column with the output of a shell command.
+/// The command runs through the system shell with the permissions of this
+/// process, so only pass commands you trust; `--allow-exec` is required.
+#[derive(Args, Debug, Clone)]
+struct PipeConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long)]
+    column: String,
+    /// Command reading a value on stdin and writing the new value to stdout
+    #[arg(long)]
+    command: String,
+    /// Run the command once per file, with one value per line on stdin and stdout
+    #[arg(long)]
+    batch: bool,
+    /// Confirm that running an external command is intended
+    #[arg(long)]
+    allow_exec: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Convert NDJSON files, one object per line, into CSV files
+#[derive(Args, Debug, Clone)]
+struct FromJsonConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long)]
+    output_dir: String,
+    /// Header order, comma-separated, defaults to the keys of the first object
+    #[arg(long, value_delimiter = ',')]
+    columns: Option<Vec<String>>,
+    /// File extensions to convert, comma-separated
+    #[arg(long, value_delimiter = ',', default_value = "jsonl,ndjson")]
+    extensions: Vec<String>,
+    /// Field delimiter of the written files, `\t` for tabs
+    #[arg(long, value_parser = parse_delimiter)]
+    delimiter: Option<u8>,
+}
+
+/// Write the data rows of each file as fixed-width text without delimiters
+#[derive(Args, Debug, Clone)]
+struct ToFixedConfig {
+    #[arg(long)]
+    path: String,
+    /// Width of each column in characters, comma-separated
+    #[arg(long, value_delimiter = ',', required = true)]
+    widths: Vec<usize>,
+    #[arg(long)]
+    output_dir: String,
+    #[arg(long, value_enum, default_value_t = FixedAlign::Left)]
+    align: FixedAlign,
+    /// Cut values wider than their column instead of failing
+    #[arg(long)]
+    truncate: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum FixedAlign {
+    Left,
+    Right,
+}
+
+/// Swap the rows and columns of a single file, so the first column becomes
+/// the header row. The whole file is held in memory while transposing
+#[derive(Args, Debug, Clone)]
+struct TransposeConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long)]
+    output: String,
+}
+
+/// Unpivot wide files: each row becomes one row per value column, holding
+/// the id columns, the value column's name and its value
+#[derive(Args, Debug, Clone)]
+struct MeltConfig {
+    #[arg(long)]
+    path: String,
+    /// Columns kept on every output row, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    id_columns: Vec<String>,
+    /// Columns turned into rows, comma-separated
+    #[arg(long, value_delimiter = ',', required_unless_present = "columns_file")]
+    value_columns: Vec<String>,
+    /// File with more columns, one per line, ignoring blank lines and `#` comments
+    #[arg(long)]
+    columns_file: Option<String>,
+    #[arg(long, default_value = "variable")]
+    var_name: String,
+    #[arg(long, default_value = "value")]
+    value_name: String,
+    #[command(flatten)]
+    output: OutputConfig,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Spread the values of a column into new columns, one row per distinct
+/// combination of the index columns. Groups and new columns keep the order
+/// they are first seen in
+#[derive(Args, Debug, Clone)]
+struct PivotConfig {
+    #[arg(long)]
+    path: String,
+    /// Grouping columns, comma-separated
+    #[arg(long, value_delimiter = ',', required_unless_present = "columns_file")]
+    index: Vec<String>,
+    /// File with more columns, one per line, ignoring blank lines and `#` comments
+    #[arg(long)]
+    columns_file: Option<String>,
+    /// Column whose values become the new headers
+    #[arg(long)]
+    column: String,
+    /// Column holding the cell values
+    #[arg(long)]
+    value: String,
+    /// What to do when a group has more than one value for a header
+    #[arg(long, value_enum, default_value_t = PivotAggregate::Error)]
+    aggregate: PivotAggregate,
+    #[command(flatten)]
+    output: OutputConfig,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum PivotAggregate {
+    First,
+    Last,
+    Error,
+}
+
+/// Find and replace within header names, leaving data rows untouched
+#[derive(Args, Debug, Clone)]
+struct ReplaceHeadersConfig {
+    #[arg(long)]
+    path: String,
+    /// Text to find, must not be empty
+    #[arg(long, value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    find: String,
+    #[arg(long)]
+    replace: String,
+    /// Treat --find as a regular expression, --replace may use $1 for groups
+    #[arg(long)]
+    regex: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Drop columns whose values repeat an earlier column in every row
+#[derive(Args, Debug, Clone)]
+struct DedupeColumnsConfig {
+    #[arg(long)]
+    path: String,
+    /// Only treat columns with the same header as duplicates
+    #[arg(long)]
+    by_name: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Trim headers and collapse their internal whitespace, suffixing names
+/// that collide afterwards with _2, _3 and so on
+#[derive(Args, Debug, Clone)]
+struct NormalizeConfig {
+    #[arg(long)]
+    path: String,
+    /// Also lowercase headers
+    #[arg(long)]
+    lower: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Check every value of a column against a regex without changing files,
+/// exiting non-zero when any fail
+#[derive(Args, Debug, Clone)]
+struct AssertConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long)]
+    column: String,
+    /// Matches anywhere in the value, anchor with ^ and $ to match whole values
+    #[arg(long)]
+    pattern: String,
+    /// Let empty values pass without matching the pattern
+    #[arg(long)]
+    allow_empty: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Clamp the numeric values of a column into a range. Empty values are left
+/// as they are
+#[derive(Args, Debug, Clone)]
+#[command(group(ArgGroup::new("bounds").required(true).multiple(true).args(["min", "max"])))]
+struct ClampConfig {
+    #[arg(long)]
+    path: String,
+    #[command(flatten)]
+    column: ColumnSelector,
+    /// Only change rows where this holds, e.g. `type==special`
+    #[arg(long = "where")]
+    row_filter: Option<RowFilter>,
+    #[arg(long, allow_negative_numbers = true)]
+    min: Option<f64>,
+    #[arg(long, allow_negative_numbers = true)]
+    max: Option<f64>,
+    /// What to do with values that aren't numbers
+    #[arg(long, value_enum, default_value_t = ClampOnError::Keep)]
+    on_error: ClampOnError,
+    /// Report how many cells would change without writing
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum ClampOnError {
+    Keep,
+    Clear,
+    Error,
+}
+
+/// Rewrite each file without changes in memory and report where the output
+/// wouldn't be byte-identical, to find the compatibility flags a tree needs
+#[derive(Args, Debug, Clone)]
+struct SelfCheckConfig {
+    #[arg(long)]
+    path: String,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Keep only the data rows in a range, e.g. 1000:2000
+#[derive(Args, Debug, Clone)]
+struct RangeConfig {
+    #[arg(long)]
+    path: String,
+    /// 1-based inclusive START:END, clamped to the rows of each file
+    #[arg(long)]
+    rows_range: RowsRange,
+    #[command(flatten)]
+    output: OutputConfig,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RowsRange {
+    start: usize,
+    end: usize,
+}
+
+impl FromStr for RowsRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s
+            .split_once(':')
+            .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)));
+        match parsed {
+            Some((start, end)) if start >= 1 && start <= end => Ok(RowsRange { start, end }),
+            _ => Err(format!(
+                "expected START:END with 1 <= START <= END, got {}",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RowsRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.start, self.end)
+    }
+}
+
+/// Obfuscate columns for sharing test fixtures. Fakes keep the length and
+/// the digit, letter and punctuation layout of each value. This is not
+/// anonymization in any security sense: shuffled values are still real and
+/// fakes can be brute-forced from the seed
+#[derive(Args, Debug, Clone)]
+struct AnonConfig {
+    #[arg(long)]
+    path: String,
+    /// Columns to obfuscate, comma-separated
+    #[arg(long, value_delimiter = ',', required = true)]
+    columns: Vec<String>,
+    #[arg(long, value_enum, default_value_t = AnonMode::Fake)]
+    mode: AnonMode,
+    /// The same seed gives the same output, and a value gets the same fake
+    /// in every file
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum AnonMode {
+    /// Replace each value with a fake derived from its hash
+    Fake,
+    /// Shuffle the values of each column among the rows of a file
+    Shuffle,
+}
+
+/// Strip diacritics, turning café into cafe. Letters without a decomposition,
+/// like ß or ø, are left as they are
+#[derive(Args, Debug, Clone)]
+struct DeaccentConfig {
+    #[arg(long)]
+    path: String,
+    /// Only this column, instead of every header and value
+    #[arg(long)]
+    column: Option<String>,
+    /// Report how many cells would change without writing
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Rename many headers in one pass, leaving data rows untouched
+#[derive(Args, Debug, Clone)]
+struct RenameManyConfig {
+    #[arg(long)]
+    path: String,
+    /// A .json object of old to new names, or a CSV file with a header row
+    /// and old and new names in its first two columns
+    #[arg(long)]
+    map: String,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Round numeric values to a fixed number of decimals, e.g. 12.3400000001
+/// to 12.34. Other values are left as they are
+#[derive(Args, Debug, Clone)]
+struct RoundConfig {
+    #[arg(long)]
+    path: String,
+    #[command(flatten)]
+    column: ColumnSelector,
+    /// Only change rows where this holds, e.g. `type==special`
+    #[arg(long = "where")]
+    row_filter: Option<RowFilter>,
+    #[arg(long)]
+    decimals: usize,
+    /// Report how many cells would change without writing
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Fill in blank header cells, leaving named columns as they are
+#[derive(Args, Debug, Clone)]
+#[command(group(ArgGroup::new("naming").required(true).args(["names", "prefix"])))]
+struct NameColumnsConfig {
+    #[arg(long)]
+    path: String,
+    /// Names for the blank headers from left to right, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    names: Vec<String>,
+    /// Name each blank header after its 1-based position, e.g. col_ for col_1
+    #[arg(long)]
+    prefix: Option<String>,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Split one file into numbered parts, each at most `--max-bytes` long
+/// including its repeated header. The source file is left as it is
+#[derive(Args, Debug, Clone)]
+struct SplitBytesConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_bytes: u64,
+    #[arg(long)]
+    output_dir: String,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Drop trailing columns that are empty in the header and every row, as
+/// left by exports with an extra comma at the end of each line
+#[derive(Args, Debug, Clone)]
+struct TrimColumnsConfig {
+    #[arg(long)]
+    path: String,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// Fill a column with the lines of a values file, one per data row in file
+/// order. Rows past the end of the values get empty cells, or fail with --strict
+#[derive(Args, Debug, Clone)]
+struct FillFromConfig {
+    #[arg(long)]
+    path: String,
+    /// Existing column to overwrite, or new column to insert
+    #[arg(long)]
+    column: String,
+    #[arg(long)]
+    values_file: String,
+    /// Where to insert a new column, 1-based or `last`
+    #[arg(long, default_value = "last")]
+    order: InsertOrder,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+/// List every header name across all files in a CSV, with how many files
+/// have it and the lowest and highest 1-based position it was seen at
+#[derive(Args, Debug, Clone)]
+struct HeaderInventoryConfig {
+    #[arg(long)]
+    path: String,
+    #[arg(long)]
+    output: String,
+    #[command(flatten)]
+    common: CommonConfig,
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(group(ArgGroup::new("field_delimiter").args(["delimiter", "tab", "semicolon", "pipe"])))]
+struct CommonConfig {
+    /// Only write the first N data rows of each file. This truncates data
+    /// and is meant for trial runs against a copy of the tree
+    #[arg(long, short = 'n')]
+    sample: Option<usize>,
+    /// 1-based line of the header, for files with title rows above it
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    header_row: u64,
+    /// Keep the lines above `--header-row` instead of dropping them
+    #[arg(long)]
+    keep_preamble: bool,
+    #[command(flatten)]
+    matching: MatchConfig,
+    /// File extensions to migrate, comma-separated
+    #[arg(long, value_delimiter = ',', default_value = "csv")]
+    extensions: Vec<String>,
+    /// Field delimiter, `\t` for tab. Defaults to tab for .tsv files and
+    /// comma for everything else
+    #[arg(long, value_parser = parse_delimiter)]
+    delimiter: Option<u8>,
+    /// Shortcut for `--delimiter '\t'`
+    #[arg(long)]
+    tab: bool,
+    /// Shortcut for `--delimiter ';'`
+    #[arg(long)]
+    semicolon: bool,
+    /// Shortcut for `--delimiter '|'`
+    #[arg(long)]
+    pipe: bool,
+    /// Character escaping quotes inside quoted fields, e.g. `\` for `\"`
+    #[arg(long, value_parser = parse_delimiter)]
+    escape: Option<u8>,
+    /// Don't treat `""` inside quoted fields as an escaped quote
+    #[arg(long)]
+    no_double_quote: bool,
+    /// Include files and directories whose name starts with a dot
+    #[arg(long)]
+    hidden: bool,
+    /// Descend into symlinked directories, which are skipped by default.
+    /// Directories already visited through another link are not repeated
+    #[arg(long)]
+    follow_symlinks: bool,
+    /// How many directory levels below `--path` to descend, 1 for no recursion
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// Skip files larger than this many bytes
+    #[arg(long)]
+    max_file_size: Option<u64>,
+    /// Fail the run when any file was skipped or any warning was printed
+    #[arg(long)]
+    strict: bool,
+    /// Treat lone CR as a record terminator, for old Mac exports
+    #[arg(long)]
+    cr_records: bool,
+    /// What to do with files missing the named column
+    #[arg(long, value_enum, default_value_t = OnMissingColumn::WarnSkip)]
+    on_missing_column: OnMissingColumn,
+    /// Log progress only every N files instead of every file
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    progress_every: u64,
+    /// Also log megabytes read out of the whole tree and the MB/s so far,
+    /// when output goes to a terminal
+    #[arg(long)]
+    progress_bytes: bool,
+    /// Record finished files here and skip files it already lists, so an
+    /// interrupted run can be resumed
+    #[arg(long)]
+    checkpoint: Option<String>,
+    /// Size in bytes of the CSV reader and writer buffers, at least 1 KiB and
+    /// 8 KiB by default. Files are read into memory whole, so this only tunes
+    /// parsing and formatting throughput
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1024..))]
+    buffer_size: Option<u64>,
+    /// Retry a file this many times when reading or writing it fails with an
+    /// IO error, for flaky network filesystems
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+    /// Milliseconds before the first retry, doubling for each one after
+    #[arg(long, default_value_t = 500)]
+    retry_delay: u64,
+    /// Skip files whose headers aren't exactly these, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    only_if_header: Vec<String>,
+    /// Accept the --only-if-header columns in any order
+    #[arg(long, requires = "only_if_header")]
+    any_header_order: bool,
+    /// Replace invalid UTF-8 with U+FFFD instead of failing the file
+    #[arg(long)]
+    lossy: bool,
+    /// Check every file's headers for the named column before writing any,
+    /// aborting with --strict when one would fail
+    #[arg(long)]
+    precheck: bool,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+struct OutputConfig {
+    /// Write results under this directory instead of migrating in place
+    #[arg(long)]
+    output_dir: Option<String>,
+    /// Write results next to each file with this inserted before the
+    /// extension, e.g. `.migrated` for data.migrated.csv
+    #[arg(long, conflicts_with = "output_dir")]
+    output_suffix: Option<String>,
+    /// Overwrite files that already exist at the --output-suffix name
+    #[arg(long, requires = "output_suffix")]
+    force: bool,
+    /// Name results after a template of `{stem}`, `{ext}` and `{parent}`,
+    /// e.g. `{parent}_{stem}.{ext}`. In place the original is renamed, with
+    /// --output-dir the copy is
+    #[arg(long, conflicts_with = "output_suffix")]
+    rename_to: Option<String>,
+}
+
+impl OutputConfig {
+    fn renamed(&self, file: &Path) -> Result<Option<String>, Box<dyn Error>> {
+        let Some(template) = &self.rename_to else {
+            return Ok(None);
+        };
+        let part = |part: Option<&OsStr>| part.unwrap_or_default().to_string_lossy().into_owned();
+        let name = template
+            .replace("{stem}", &part(file.file_stem()))
+            .replace("{ext}", &part(file.extension()))
+            .replace("{parent}", &part(file.parent().and_then(Path::file_name)));
+        let mut components = Path::new(&name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) if !name.contains(['{', '}']) => Ok(Some(name)),
+            _ => Err(format!("--rename-to {} gives {:?}, not a file name", template, name).into()),
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+#[group(required = true, multiple = false)]
+struct ColumnSelector {
+    #[arg(long)]
+    column: Option<String>,
+    /// Every column whose header matches this regex, instead of --column
+    #[arg(long, value_parser = Regex::new)]
+    column_regex: Option<Regex>,
+}
+
+impl ColumnSelector {
+    /// Indexes of the selected columns, empty when none are found.
+    fn find(
+        &self,
+        headers: &StringRecord,
+        matching: &MatchConfig,
+    ) -> Result<Vec<usize>, Box<dyn Error>> {
+        let Some(regex) = &self.column_regex else {
+            let column = self.column.as_deref().unwrap_or_default();
+            return Ok(matching.find_column(headers, column)?.into_iter().collect());
+        };
+        let indexes: Vec<usize> = (0..headers.len())
+            .filter(|&i| regex.is_match(&headers[i]))
+            .collect();
+        if !indexes.is_empty() {
+            let names: Vec<&str> = indexes.iter().map(|&i| &headers[i]).collect();
+            println!("Columns {}", names.join(", "));
+        }
+        Ok(indexes)
+    }
+}
+
+impl fmt::Display for ColumnSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.column, &self.column_regex) {
+            (_, Some(regex)) => write!(f, "/{}/", regex),
+            (column, None) => write!(f, "{}", column.as_deref().unwrap_or_default()),
+        }
+    }
+}
+
+/// A `--where` predicate: a column, `==`, `!=` or `=~` for a regex, and
+/// the value to compare with, e.g. `type==special`
+#[derive(Debug, Clone)]
+struct RowFilter {
+    column: String,
+    op: RowFilterOp,
+}
+
+#[derive(Debug, Clone)]
+enum RowFilterOp {
+    Equals(String),
+    NotEquals(String),
+    Matches(Regex),
+}
+
+impl FromStr for RowFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((at, op)) = ["==", "!=", "=~"]
+            .iter()
+            .filter_map(|op| s.find(op).map(|at| (at, *op)))
+            .min()
+        else {
+            return Err(format!("expected <column>==, != or =~<value>, got {}", s));
+        };
+        let (column, value) = (&s[..at], &s[at + op.len()..]);
+        if column.is_empty() {
+            return Err(format!("missing column in {}", s));
+        }
+        let op = match op {
+            "==" => RowFilterOp::Equals(value.to_string()),
+            "!=" => RowFilterOp::NotEquals(value.to_string()),
+            _ => RowFilterOp::Matches(Regex::new(value).map_err(|e| e.to_string())?),
+        };
+        Ok(RowFilter {
+            column: column.to_string(),
+            op,
+        })
+    }
+}
+
+/// Lets through the rows matching an optional `--where`, counting them.
+struct RowGate<'a> {
+    filter: Option<(usize, &'a RowFilter)>,
+    matched: usize,
+}
+
+impl<'a> RowGate<'a> {
+    fn new(
+        filter: Option<&'a RowFilter>,
+        headers: &StringRecord,
+        matching: &MatchConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        let filter = match filter {
+            Some(filter) => match matching.find_column(headers, &filter.column)? {
+                Some(index) => Some((index, filter)),
+                None => return Err(format!("Column {} in --where not found", filter.column).into()),
+            },
+            None => None,
+        };
+        Ok(Self { filter, matched: 0 })
+    }
+
+    fn admits(&mut self, record: &StringRecord) -> bool {
+        let Some((index, filter)) = self.filter else {
+            return true;
+        };
+        let value = record.get(index).unwrap_or_default();
+        let admitted = match &filter.op {
+            RowFilterOp::Equals(expected) => value == expected,
+            RowFilterOp::NotEquals(expected) => value != expected,
+            RowFilterOp::Matches(regex) => regex.is_match(value),
+        };
+        self.matched += admitted as usize;
+        admitted
+    }
+
+    fn report(&self) {
+        if self.filter.is_some() {
+            println!("{} rows matched --where", self.matched);
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
+struct MatchConfig {
+    /// Match column names case-insensitively. Headers differing only by
+    /// case make the match ambiguous and fail the file
+    #[arg(long)]
+    ignore_case: bool,
+    /// Ignore leading and trailing whitespace when matching column names
+    #[arg(long)]
+    trim_match: bool,
+}
+
+/// Append columns from the right file to matching rows of the left file.
+/// Duplicate keys in the right file produce one output row per match.
+#[derive(Args, Debug, Clone)]
+struct JoinConfig {
+    #[arg(long)]
+    left: String,
+    #[arg(long)]
+    right: String,
+    #[arg(long)]
+    on: String,
+    #[arg(long)]
+    output: String,
+    #[arg(long, value_enum, default_value_t = JoinHow::Inner)]
+    how: JoinHow,
+    #[command(flatten)]
+    matching: MatchConfig,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum JoinHow {
+    Inner,
+    Left,
+}
+
+/// Compare two files, exiting non-zero when they differ. Rows are matched
+/// by the `--key` column when given, otherwise by position.
+#[derive(Args, Debug, Clone)]
+struct DiffConfig {
+    #[arg(long)]
+    a: String,
+    #[arg(long)]
+    b: String,
+    #[arg(long)]
+    key: Option<String>,
+    #[command(flatten)]
+    matching: MatchConfig,
+}
+
+impl Default for CommonConfig {
+    fn default() -> Self {
+        Self {
+            sample: None,
+            header_row: 1,
+            keep_preamble: false,
+            matching: MatchConfig::default(),
+            extensions: vec!["csv".to_string()],
+            delimiter: None,
+            tab: false,
+            semicolon: false,
+            pipe: false,
+            escape: None,
+            no_double_quote: false,
+            hidden: false,
+            follow_symlinks: false,
+            max_depth: None,
+            max_file_size: None,
+            strict: false,
+            cr_records: false,
+            progress_every: 1,
+            progress_bytes: false,
+            checkpoint: None,
+            buffer_size: None,
+            retries: 0,
+            retry_delay: 500,
+            only_if_header: vec![],
+            any_header_order: false,
+            lossy: false,
+            precheck: false,
+            on_missing_column: OnMissingColumn::WarnSkip,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum OnMissingColumn {
+    Error,
+    WarnSkip,
+    SilentSkip,
+}
+
+fn parse_delimiter(delimiter: &str) -> Result<u8, String> {
+    match delimiter {
+        "\\t" => Ok(b'\t'),
+        _ if delimiter.len() == 1 => Ok(delimiter.as_bytes()[0]),
+        _ => Err(format!(
+            "expected a single ASCII character, got {}",
+            delimiter
+        )),
+    }
+}
+
+impl CommonConfig {
+    fn delimiter(&self, path: &Path) -> u8 {
+        match self.delimiter {
+            Some(delimiter) => delimiter,
+            None if self.tab => b'\t',
+            None if self.semicolon => b';',
+            None if self.pipe => b'|',
+            None if path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("tsv")) =>
+            {
+                b'\t'
+            }
+            None => b',',
+        }
+    }
+
+    /// Files a previous run recorded in --checkpoint as done.
+    fn checkpointed(&self) -> Result<HashSet<PathBuf>, Box<dyn Error>> {
+        let Some(checkpoint) = &self.checkpoint else {
+            return Ok(HashSet::new());
+        };
+        match fs::read_to_string(checkpoint) {
+            Ok(content) => Ok(content.lines().map(PathBuf::from).collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+            Err(e) => Err(e).with_path(checkpoint),
+        }
+    }
+
+    fn read(&self, path: &Path) -> Result<String, Box<dyn Error>> {
+        self.decode(path, fs::read(path)?)
+    }
+
+    /// The text of a file read as `bytes`, as `read` would return it.
+    fn decode(&self, path: &Path, bytes: Vec<u8>) -> Result<String, Box<dyn Error>> {
+        let content = if self.lossy {
+            let replaced = bytes
+                .utf8_chunks()
+                .filter(|chunk| !chunk.invalid().is_empty())
+                .count();
+            if replaced > 0 {
+                warn(format!(
+                    "{} invalid UTF-8 sequences replaced in {:?}",
+                    replaced, path
+                ));
+            }
+            String::from_utf8_lossy(&bytes).into_owned()
+        } else {
+            String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        };
+        Ok(self.normalize_records(&content).into_owned())
+    }
+
+    fn normalize_records<'a>(&self, content: &'a str) -> Cow<'a, str> {
+        if !self.cr_records || !content.contains('\r') {
+            return Cow::Borrowed(content);
+        }
+        let mut normalized = String::with_capacity(content.len());
+        let mut chars = content.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' if chars.peek() == Some(&'\n') => {}
+                '\r' => normalized.push('\n'),
+                _ => normalized.push(c),
+            }
+        }
+        Cow::Owned(normalized)
+    }
+
+    /// Readers only parse content already read into memory, never the file
+    /// itself, so replacing the file can't clobber input not yet read.
+    fn reader<'a>(&self, path: &Path, data: &'a str) -> csv::Reader<&'a [u8]> {
+        self.reader_builder(path).from_reader(data.as_bytes())
+    }
+
+    fn reader_builder(&self, path: &Path) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter(path))
+            .escape(self.escape)
+            .double_quote(!self.no_double_quote);
+        if let Some(buffer_size) = self.buffer_size {
+            builder.buffer_capacity(buffer_size as usize);
+        }
+        builder
+    }
+
+    fn sample_size(&self) -> usize {
+        self.sample.unwrap_or(usize::MAX)
+    }
+
+    fn split_preamble<'a>(&self, content: &'a str) -> (&'a str, &'a str) {
+        let mut offset = 0;
+        for _ in 1..self.header_row {
+            match content[offset..].find('\n') {
+                Some(i) => offset += i + 1,
+                None => offset = content.len(),
+            }
+        }
+        content.split_at(offset)
+    }
+
+    /// Writers only fill a buffer; files are replaced through [`write_atomic`]
+    /// once the whole output exists.
+    fn writer(&self, path: &Path, preamble: &str) -> csv::Writer<Vec<u8>> {
+        let mut output = vec![];
+        if self.keep_preamble {
+            output.extend_from_slice(preamble.as_bytes());
+        }
+        let mut builder = csv::WriterBuilder::new();
+        builder
+            .delimiter(self.delimiter(path))
+            .escape(self.escape.unwrap_or(b'\\'))
+            .double_quote(!self.no_double_quote);
+        if let Some(buffer_size) = self.buffer_size {
+            builder.buffer_capacity(buffer_size as usize);
+        }
+        builder.from_writer(output)
+    }
+
+    fn drops_preamble(&self, preamble: &str) -> bool {
+        !preamble.is_empty() && !self.keep_preamble
+    }
+
+    fn header_expected(&self, path: &Path) -> Result<bool, Box<dyn Error>> {
+        if self.only_if_header.is_empty() {
+            return Ok(true);
+        }
+        let content = self.read(path)?;
+        let (_, data) = self.split_preamble(&content);
+        let headers = self.reader(path, data).headers()?.clone();
+        if headers.len() != self.only_if_header.len() {
+            return Ok(false);
+        }
+        if !self.any_header_order {
+            return Ok(headers
+                .iter()
+                .zip(&self.only_if_header)
+                .all(|(header, expected)| self.matching.matches(header, expected)));
+        }
+        let mut unmatched: Vec<&str> = headers.iter().collect();
+        for expected in &self.only_if_header {
+            match unmatched
+                .iter()
+                .position(|header| self.matching.matches(header, expected))
+            {
+                Some(i) => unmatched.swap_remove(i),
+                None => return Ok(false),
+            };
+        }
+        Ok(true)
+    }
+
+    fn missing_column(&self, column: &str) -> Result<FileOutcome, Box<dyn Error>> {
+        match self.on_missing_column {
+            OnMissingColumn::Error => Err(format!("Column {} not found", column).into()),
+            OnMissingColumn::WarnSkip => {
+                Ok(FileOutcome::Skipped(format!("column {} not found", column)))
+            }
+            OnMissingColumn::SilentSkip => Ok(FileOutcome::Ignored),
+        }
+    }
+}
+
+impl MatchConfig {
+    fn matcher(&self) -> ColumnMatcher {
+        ColumnMatcher {
+            ignore_case: self.ignore_case,
+            trim: self.trim_match,
+        }
+    }
+
+    fn matches(&self, header: &str, column: &str) -> bool {
+        self.matcher().matches(header, column)
+    }
+
+    fn find_column(
+        &self,
+        headers: &StringRecord,
+        column: &str,
+    ) -> Result<Option<usize>, Box<dyn Error>> {
+        Ok(self.matcher().find_column(headers, column)?)
+    }
+
+    fn find_occurrence(
+        &self,
+        headers: &StringRecord,
+        column: &str,
+        occurrence: usize,
+    ) -> Result<Option<usize>, Box<dyn Error>> {
+        Ok(self
+            .matcher()
+            .find_occurrence(headers, column, occurrence)?)
+    }
+}
+
+thread_local! {
+    // per thread and reset by each run, so tests don't see each other's tallies
+    static WARNINGS: Cell<usize> = const { Cell::new(0) };
+    static MIGRATED: Cell<usize> = const { Cell::new(0) };
+    static PREVIEWED: Cell<usize> = const { Cell::new(0) };
+    static UNCHANGED: Cell<usize> = const { Cell::new(0) };
+    static SKIPPED: Cell<usize> = const { Cell::new(0) };
+}
+
+fn warn(message: impl fmt::Display) {
+    WARNINGS.with(|warnings| warnings.set(warnings.get() + 1));
+    println!("{}", message.to_string().yellow());
+}
+
+/// Refuse to write `output` over one of the files it is built from.
+fn check_output_not_input(output: &str, inputs: &[&String]) -> Result<(), Box<dyn Error>> {
+    // an output that doesn't exist yet can't be an input
+    let Ok(output_path) = fs::canonicalize(output) else {
+        return Ok(());
+    };
+    for input in inputs {
+        if fs::canonicalize(input).is_ok_and(|input| input == output_path) {
+            return Err(format!("Output {} is the input {}", output, input).into());
+        }
+    }
+    Ok(())
+}
+
+/// Whether an error comes from IO that may succeed when tried again, as
+/// opposed to a file that can't be parsed or migrated.
+fn is_transient(err: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            return matches!(
+                err.kind(),
+                io::ErrorKind::Interrupted
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::ResourceBusy
+                    | io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+            );
+        }
+        if let Some(csv::ErrorKind::Io(err)) =
+            err.downcast_ref::<csv::Error>().map(csv::Error::kind)
+        {
+            return is_transient(err);
+        }
+        source = err.source();
+    }
+    false
+}
+
+fn progress_bytes(read: u64, total: u64, elapsed: Duration) -> String {
+    let megabytes = |bytes: u64| bytes as f64 / 1_000_000.0;
+    let percent = match total {
+        0 => 100.0,
+        total => read as f64 * 100.0 / total as f64,
+    };
+    let seconds = elapsed.as_secs_f64();
+    let rate = if seconds > 0.0 {
+        megabytes(read) / seconds
+    } else {
+        0.0
+    };
+    format!(
+        "{:.1} of {:.1} MB read ({:.0}%), {:.1} MB/s",
+        megabytes(read),
+        megabytes(total),
+        percent,
+        rate
+    )
+}
+
+/// The final line of a successful run. A failing file aborts the run, so
+/// there is no failed count to report here.
+fn summary() -> String {
+    let [migrated, previewed, unchanged, skipped] =
+        [&MIGRATED, &PREVIEWED, &UNCHANGED, &SKIPPED].map(|count| count.with(Cell::get));
+    let warnings = WARNINGS.with(Cell::get);
+    let warnings = match warnings {
+        0 => String::new(),
+        warnings => format!(" with {} warnings", warnings).yellow().to_string(),
+    };
+    // commands that don't migrate files one by one have nothing to tally
+    if migrated + previewed + unchanged + skipped == 0 {
+        return format!("{}{}", "Migration done".green(), warnings);
+    }
+    let skipped = match skipped {
+        0 => "0 skipped".normal(),
+        skipped => format!("{} skipped", skipped).yellow(),
+    };
+    let previewed = match previewed {
+        0 => String::new(),
+        previewed => format!(", {} would change", previewed),
+    };
+    format!(
+        "Done: {}{}, {} unchanged, {}{}",
+        format!("{} migrated", migrated).green(),
+        previewed,
+        unchanged,
+        skipped,
+        warnings
+    )
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => println!("{}", summary()),
+        Err(err) => {
+            println!("{} {}", "Migration failed:".red(), err);
+            process::exit(1);
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    for count in [&WARNINGS, &MIGRATED, &PREVIEWED, &UNCHANGED, &SKIPPED] {
+        count.with(|count| count.set(0));
+    }
+    match cli.command {
+        Commands::Insert(insert_config) => InsertMigration::new(insert_config).run()?,
+        Commands::Reorder(reorder_config) => ReorderMigration::new(reorder_config).run()?,
+        Commands::Join(join_config) => JoinMigration::new(join_config).run()?,
+        Commands::Diff(diff_config) => DiffMigration::new(diff_config).run()?,
+        Commands::Flatten(flatten_config) => FlattenMigration::new(flatten_config).run()?,
+        Commands::InsertRow(insert_row_config) => {
+            InsertRowMigration::new(insert_row_config).run()?
+        }
+        Commands::Head(head_config) => HeadMigration::new(head_config).run()?,
+        Commands::Range(range_config) => RangeMigration::new(range_config).run()?,
+        Commands::Anonymize(anon_config) => AnonymizeMigration::new(anon_config).run()?,
+        Commands::Deaccent(deaccent_config) => DeaccentMigration::new(deaccent_config).run()?,
+        Commands::RenameMany(rename_many_config) => {
+            RenameManyMigration::new(rename_many_config).run()?
+        }
+        Commands::Round(round_config) => RoundMigration::new(round_config).run()?,
+        Commands::NameColumns(name_columns_config) => {
+            NameColumnsMigration::new(name_columns_config).run()?
+        }
+        Commands::Unreorder(unreorder_config) => UnreorderMigration::new(unreorder_config).run()?,
+        Commands::SplitBytes(split_bytes_config) => {
+            SplitBytesMigration::new(split_bytes_config).run()?
+        }
+        Commands::TrimColumns(trim_columns_config) => {
+            TrimColumnsMigration::new(trim_columns_config).run()?
+        }
+        Commands::FillFrom(fill_from_config) => FillFromMigration::new(fill_from_config).run()?,
+        Commands::HeaderInventory(header_inventory_config) => {
+            HeaderInventoryMigration::new(header_inventory_config).run()?
+        }
+        Commands::Tail(tail_config) => TailMigration::new(tail_config).run()?,
+        Commands::Manifest(manifest_config) => ManifestMigration::new(manifest_config).run()?,
+        Commands::Distinct(distinct_config) => DistinctMigration::new(distinct_config).run()?,
+        Commands::Coalesce(mut coalesce_config) => {
+            merge_columns_file(&mut coalesce_config.from, &coalesce_config.columns_file)?;
+            CoalesceMigration::new(coalesce_config).run()?
+        }
+        Commands::Pad(pad_config) => PadMigration::new(pad_config).run()?,
+        Commands::Slice(slice_config) => SliceMigration::new(slice_config).run()?,
+        Commands::Pipe(pipe_config) => PipeMigration::new(pipe_config).run()?,
+        Commands::FromJson(from_json_config) => FromJsonMigration::new(from_json_config).run()?,
+        Commands::ToFixed(to_fixed_config) => ToFixedMigration::new(to_fixed_config).run()?,
+        Commands::Transpose(transpose_config) => TransposeMigration::new(transpose_config).run()?,
+        Commands::Melt(mut melt_config) => {
+            merge_columns_file(&mut melt_config.value_columns, &melt_config.columns_file)?;
+            MeltMigration::new(melt_config).run()?
+        }
+        Commands::Pivot(mut pivot_config) => {
+            merge_columns_file(&mut pivot_config.index, &pivot_config.columns_file)?;
+            PivotMigration::new(pivot_config).run()?
+        }
+        Commands::ReplaceHeaders(replace_headers_config) => {
+            ReplaceHeadersMigration::new(replace_headers_config).run()?
+        }
+        Commands::DedupeColumns(dedupe_columns_config) => {
+            DedupeColumnsMigration::new(dedupe_columns_config).run()?
+        }
+        Commands::NormalizeHeaders(normalize_config) => {
+            NormalizeHeadersMigration::new(normalize_config).run()?
+        }
+        Commands::Assert(assert_config) => AssertMigration::new(assert_config).run()?,
+        Commands::Clamp(clamp_config) => ClampMigration::new(clamp_config).run()?,
+        Commands::SelfCheck(self_check_config) => {
+            SelfCheckMigration::new(self_check_config).run()?
+        }
+    };
+
+    Ok(())
+}
+
+fn merge_columns_file(
+    columns: &mut Vec<String>,
+    columns_file: &Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(columns_file) = columns_file {
+        let content = fs::read_to_string(columns_file).with_path(columns_file)?;
+        columns.extend(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from),
+        );
+    }
+    if columns.is_empty() {
+        return Err("No columns given".into());
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+struct InsertMigration {
+    config: InsertConfig,
+}
+impl Migration for InsertMigration {
+    type ConfigType = InsertConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let InsertConfig {
+            path,
+            column,
+            default_value,
+            template,
+            default_env,
+            default_builtin,
+            order,
+            ..
+        } = &self.config;
+        let source = match (default_value, template, default_env, default_builtin) {
+            (Some(value), ..) => value.clone(),
+            (_, Some(template), ..) => template.clone(),
+            (_, _, Some(var), _) => format!("${}", var),
+            (.., builtin) => format!("{:?}", builtin.unwrap()).to_lowercase(),
+        };
+        println!(
+            "Inserting {} with default value {} as #{} in path {}",
+            &column.blue(),
+            &source.blue(),
+            &order.to_string().blue(),
+            &path.blue()
+        );
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.precheck(&files, &self.config.common, column, false)?;
+        let value = self.insert_value(&files)?;
+        self.migrate_files(&files, &self.config.common, |file| {
+            self.insert_column(file, column, &value, *order)
+        })
+    }
+}
+
+impl InsertMigration {
+    fn insert_value(&self, files: &[PathBuf]) -> Result<InsertValue, Box<dyn Error>> {
+        let InsertConfig {
+            default_value,
+            template,
+            default_env,
+            default_builtin,
+            ..
+        } = &self.config;
+        if let Some(template) = template {
+            let template = Template::parse(template)?;
+            let matching = &self.config.common.matching;
+            let common = &self.config.common;
+            for file in files {
+                let content = common.read(file).with_path(file)?;
+                let (_, data) = common.split_preamble(&content);
+                let headers = common.reader(file, data).headers()?.clone();
+                for column in template.columns() {
+                    if matching.find_column(&headers, column)?.is_none() {
+                        return Err(
+                            format!("Unknown template column {} in {:?}", column, file).into()
+                        );
+                    }
+                }
+            }
+            return Ok(InsertValue::Template(template));
+        }
+        if let Some(var) = default_env {
+            let value = env::var(var).map_err(|e| format!("{}: {}", var, e))?;
+            return Ok(InsertValue::Constant(value));
+        }
+        let started = Local::now();
+        Ok(match default_builtin {
+            Some(Builtin::Now) => {
+                InsertValue::Constant(started.to_rfc3339_opts(SecondsFormat::Secs, false))
+            }
+            Some(Builtin::Date) => InsertValue::Constant(started.date_naive().to_string()),
+            Some(Builtin::Uuid) => InsertValue::Uuid,
+            None => InsertValue::Constant(default_value.clone().unwrap_or_default()),
+        })
+    }
+
+    fn insert_column(
+        &self,
+        path: &PathBuf,
+        column: &str,
+        value: &InsertValue,
+        order: InsertOrder,
+    ) -> Result<FileOutcome, Box<dyn Error>> {
+        let raw = fs::read(path)?;
+        let content = self.config.common.decode(path, raw.clone())?;
+        let (preamble, data) = self.config.common.split_preamble(&content);
+        let mut reader = self.config.common.reader(path, data);
+        let mut writer = self.config.common.writer(path, preamble);
+
+        // set headers
+        let headers = reader.headers()?.clone();
+        let index = match order {
+            InsertOrder::Position(order) => usize::try_from(order - 1).unwrap_or(usize::MAX),
+            InsertOrder::Last => headers.len(),
+        };
+        if index > headers.len() {
+            return Err(format!(
+                "Order {} out of range for {:?} with {} columns",
+                order,
+                path,
+                headers.len()
+            )
+            .into());
+        }
+        let mut new_headers: Vec<&str> = headers.iter().collect();
+        new_headers.insert(index, column);
+        if self.config.diff_columns {
+            return Ok(FileOutcome::preview(
+                self.report_column_diff(&headers, &new_headers),
+            ));
+        }
+        writer.write_record(&new_headers)?;
+        if self.config.only_header {
+            let output = self.copy_data_rows(data, &reader, writer)?;
+            let changed = self.write_if_changed(path, &content, output)?;
+            return self.verified(path, &raw, &content, (index, column), changed);
+        }
+
+        // set values
+        for record in reader.records().take(self.config.common.sample_size()) {
+            let record = record?;
+            let default_value = value.render(&headers, &record, &self.config.common.matching);
+            let mut new_record: Vec<&str> = record.iter().collect();
+            new_record.insert(index.min(new_record.len()), &default_value);
+            writer.write_record(&new_record)?;
+        }
+
+        let changed = self.write_if_changed(path, &content, writer.into_inner()?)?;
+        self.verified(path, &raw, &content, (index, column), changed)
+    }
+
+    fn verified(
+        &self,
+        path: &PathBuf,
+        raw: &[u8],
+        original: &str,
+        (index, column): (usize, &str),
+        changed: bool,
+    ) -> Result<FileOutcome, Box<dyn Error>> {
+        if !changed || !self.config.verify_after {
+            return Ok(changed.into());
+        }
+        let only_header = self.config.only_header;
+        self.verify_written(
+            path,
+            raw,
+            original,
+            &self.config.common,
+            (index, column),
+            |o, w| {
+                if only_header {
+                    return o == w;
+                }
+                // dropping the inserted field must give back the original row
+                let mut fields: Vec<&str> = w.iter().collect();
+                if fields.len() != o.len() + 1 {
+                    return false;
+                }
+                fields.remove(index.min(o.len()));
+                fields.into_iter().eq(o.iter())
+            },
+        )?;
+        Ok(changed.into())
+    }
+}
+
+enum InsertValue {
+    Constant(String),
+    Template(Template),
+    Uuid,
+}
+
+impl InsertValue {
+    fn render(
+        &self,
+        headers: &StringRecord,
+        record: &StringRecord,
+        matching: &MatchConfig,
+    ) -> String {
+        match self {
+            InsertValue::Constant(value) => value.clone(),
+            InsertValue::Template(template) => template.render(headers, record, matching),
+            InsertValue::Uuid => Uuid::new_v4().to_string(),
+        }
+    }
+}
+
+enum TemplatePart {
+    Literal(String),
+    Column(String),
+}
+
+struct Template {
+    parts: Vec<TemplatePart>,
+}
+
+impl Template {
+    fn parse(template: &str) -> Result<Self, Box<dyn Error>> {
+        let mut parts = vec![];
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or(format!("Unclosed placeholder in template {}", template))?;
+            if start > 0 {
+                parts.push(TemplatePart::Literal(rest[..start].to_string()));
+            }
+            parts.push(TemplatePart::Column(
+                rest[start + 1..start + end].to_string(),
+            ));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Literal(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    fn columns(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            TemplatePart::Column(column) => Some(column.as_str()),
+            TemplatePart::Literal(_) => None,
+        })
+    }
+
+    fn render(
+        &self,
+        headers: &StringRecord,
+        record: &StringRecord,
+        matching: &MatchConfig,
+    ) -> String {
+        let mut value = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(literal) => value.push_str(literal),
+                TemplatePart::Column(column) => {
+                    let field = headers
+                        .iter()
+                        .position(|h| matching.matches(h, column))
+                        .and_then(|i| record.get(i));
+                    value.push_str(field.unwrap_or_default());
+                }
+            }
+        }
+        value
+    }
+}
+
+struct ReorderMigration {
+    config: ReorderConfig,
+}
+impl Migration for ReorderMigration {
+    type ConfigType = ReorderConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let ReorderConfig {
+            path,
+            column,
+            order,
+            ..
+        } = &self.config;
+        println!(
+            "Reordering {} to #{} path {}",
+            &column.blue(),
+            &order.to_string().blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.precheck(&files, &self.config.common, column, true)?;
+        self.migrate_files(&files, &self.config.common, |file| {
+            self.shift_column(file, column, *order)
+        })
+    }
+}
+
+impl ReorderMigration {
+    fn shift_column(
+        &self,
+        path: &PathBuf,
+        column: &String,
+        order: i32,
+    ) -> Result<FileOutcome, Box<dyn Error>> {
+        let raw = fs::read(path)?;
+        let content = self.config.common.decode(path, raw.clone())?;
+        let (preamble, data) = self.config.common.split_preamble(&content);
+        let mut reader = self.config.common.reader(path, data);
+
+        // headers
+        let original_headers = reader.headers()?.clone();
+        if order < 1 || order as usize > original_headers.len() {
+            return Err(format!(
+                "Order {} out of range for {:?} with {} columns",
+                order,
+                path,
+                original_headers.len()
+            )
+            .into());
+        }
+        let target_index = (order - 1) as usize;
+        let Some(target_header_index) = self.config.common.matching.find_occurrence(
+            &original_headers,
+            column,
+            self.config.occurrence as usize,
+        )?
+        else {
+            return self.config.common.missing_column(column);
+        };
+        if target_header_index == target_index && !self.config.common.drops_preamble(preamble) {
+            println!(
+                "{}",
+                format!("Column {} already on #{}", column, order).yellow()
+            );
+            return Ok(FileOutcome::Unchanged);
+        }
+        let mut writer = self.config.common.writer(path, preamble);
+
+        // after the removal, target_index addresses the shortened record, so
+        // moves by one position in either direction land exactly on #order
+        let mut new_headers: Vec<&str> = original_headers.iter().collect();
+        let target_header = new_headers.remove(target_header_index);
+        new_headers.insert(target_index, target_header);
+        if self.config.diff_columns {
+            return Ok(FileOutcome::preview(
+                self.report_column_diff(&original_headers, &new_headers),
+            ));
+        }
+        writer.write_record(&new_headers)?;
+        if self.config.only_header {
+            let output = self.copy_data_rows(data, &reader, writer)?;
+            let changed = self.write_if_changed(path, &content, output)?;
+            let outcome = self.verified(path, &raw, &content, (target_index, column), changed)?;
+            return self.recorded(path, &original_headers, &new_headers, outcome);
+        }
+
+        // values
+        for original_record in reader.records().take(self.config.common.sample_size()) {
+            let original_record = original_record?;
+            let mut new_record = original_record.iter().collect::<Vec<&str>>();
+            if target_header_index >= new_record.len() {
+                return Err(format!("Value to migrate not found in {:?}", path).into());
+            }
+            let target_value = new_record.remove(target_header_index);
+            new_record.insert(target_index.min(new_record.len()), target_value);
+            writer.write_record(&new_record)?;
+        }
+
+        let changed = self.write_if_changed(path, &content, writer.into_inner()?)?;
+        let outcome = self.verified(path, &raw, &content, (target_index, column), changed)?;
+        self.recorded(path, &original_headers, &new_headers, outcome)
+    }
+
+    fn recorded(
+        &self,
+        path: &Path,
+        before: &StringRecord,
+        after: &[&str],
+        outcome: FileOutcome,
+    ) -> Result<FileOutcome, Box<dyn Error>> {
+        let (Some(record), FileOutcome::Changed) = (&self.config.record_permutation, &outcome)
+        else {
+            return Ok(outcome);
+        };
+        // canonical, so Unreorder finds the file however --path is spelled
+        let entry = PermutationEntry {
+            path: fs::canonicalize(path)?.display().to_string(),
+            before: before.iter().map(String::from).collect(),
+            after: after.iter().map(|header| header.to_string()).collect(),
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(record)
+            .with_path(record)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        Ok(outcome)
+    }
+
+    fn verified(
+        &self,
+        path: &PathBuf,
+        raw: &[u8],
+        original: &str,
+        (index, column): (usize, &str),
+        changed: bool,
+    ) -> Result<FileOutcome, Box<dyn Error>> {
+        if !changed || !self.config.verify_after {
+            return Ok(changed.into());
+        }
+        let only_header = self.config.only_header;
+        self.verify_written(
+            path,
+            raw,
+            original,
+            &self.config.common,
+            (index, column),
+            |o, w| {
+                if only_header {
+                    return o == w;
+                }
+                // a moved field leaves every row with the same multiset of fields
+                let mut original_fields: Vec<&str> = o.iter().collect();
+                let mut written_fields: Vec<&str> = w.iter().collect();
+                original_fields.sort_unstable();
+                written_fields.sort_unstable();
+                original_fields == written_fields
+            },
+        )?;
+        Ok(changed.into())
+    }
+}
+
+struct FlattenMigration {
+    config: FlattenConfig,
+}
+impl Migration for FlattenMigration {
+    type ConfigType = FlattenConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let FlattenConfig {
+            path,
+            column,
+            replacement,
+            ..
+        } = &self.config;
+        println!(
+            "Flattening line breaks in {} with {:?} in path {}",
+            column.as_deref().unwrap_or("all columns").blue(),
+            replacement,
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_cells(
+            &files,
+            &self.config.common,
+            "flattened",
+            self.config.dry_run,
+            |file| self.flatten_file(file),
+        )
+    }
+}
+
+impl FlattenMigration {
+    fn flatten_file(&self, path: &PathBuf) -> Result<(FileOutcome, usize), Box<dyn Error>> {
+        let FlattenConfig {
+            column,
+            replacement,
+            dry_run,
+            ..
+        } = &self.config;
+        let content = self.config.common.read(path)?;
+        let (preamble, data) = self.config.common.split_preamble(&content);
+        let mut reader = self.config.common.reader(path, data);
+        let mut writer = self.config.common.writer(path, preamble);
+
+        // headers
+        let headers = reader.headers()?.clone();
+        let column_index = match column {
+            Some(column) => match self.config.common.matching.find_column(&headers, column)? {
+                Some(index) => Some(index),
+                None => return Ok((self.config.common.missing_column(column)?, 0)),
+            },
+            None => None,
+        };
+        writer.write_record(&headers)?;
+
+        // values
+        let mut flattened = 0;
+        for record in reader.records().take(self.config.common.sample_size()) {
+            let record = record?;
+            let mut new_record = StringRecord::new();
+            for (i, field) in record.iter().enumerate() {
+                let targeted = column_index.is_none_or(|index| index == i);
+                if targeted && field.contains(['\r', '\n']) {
+                    flattened += 1;
+                    new_record.push_field(
+                        &field
+                            .replace("\r\n", replacement)
+                            .replace(['\r', '\n'], replacement),
+                    );
+                } else {
+                    new_record.push_field(field);
+                }
+            }
+            writer.write_record(&new_record)?;
+        }
+
+        let outcome = self.write_unless_dry_run(path, &content, writer.into_inner()?, *dry_run)?;
+        Ok((outcome, flattened))
+    }
+}
+
+struct InsertRowMigration {
+    config: InsertRowConfig,
+}
+impl Migration for InsertRowMigration {
+    type ConfigType = InsertRowConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let InsertRowConfig {
+            path, values, at, ..
+        } = &self.config;
+        println!(
+            "Inserting row {} as #{} in path {}",
+            &values.blue(),
+            &at.to_string().blue(),
+            &path.blue()
+        );
+
+        let row = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(values.as_bytes())
+            .records()
+            .next()
+            .ok_or("No values to insert")??;
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_files(&files, &self.config.common, |file| {
+            self.insert_row(file, &row)
+        })
+    }
+}
+
+impl InsertRowMigration {
+    fn insert_row(
+        &self,
+        path: &PathBuf,
+        row: &StringRecord,
+    ) -> Result<FileOutcome, Box<dyn Error>> {
+        let content = self.config.common.read(path)?;
+        let (preamble, data) = self.config.common.split_preamble(&content);
+        let mut reader = self.config.common.reader(path, data);
+        let mut writer = self.config.common.writer(path, preamble);
+
+        // headers
+        let headers = reader.headers()?.clone();
+        if headers.len() != row.len() {
+            return Ok(FileOutcome::Skipped(format!(
+                "{} values for {} columns",
+                row.len(),
+                headers.len()
+            )));
+        }
+        writer.write_record(&headers)?;
+
+        // values
+        let at = self.config.at as usize - 1;
+        let mut rows = 0;
+        for record in reader.records().take(self.config.common.sample_size()) {
+            if rows == at {
+                writer.write_record(row)?;
+            }
+            writer.write_record(&record?)?;
+            rows += 1;
+        }
+        if rows == at {
+            writer.write_record(row)?;
+        } else if rows < at {
+            return Ok(FileOutcome::Skipped(format!("only {} data rows", rows)));
+        }
+
+        Ok(self
+            .write_if_changed(path, &content, writer.into_inner()?)?
+            .into())
+    }
+}
+
+struct HeadMigration {
+    config: HeadConfig,
+}
+impl Migration for HeadMigration {
+    type ConfigType = HeadConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let HeadConfig { path, rows, .. } = &self.config;
+        println!(
+            "Keeping the first {} rows in path {}",
+            &rows.to_string().blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.check_renames(path, &files, &self.config.output)?;
+        self.migrate_files(&files, &self.config.common, |file| self.head(file))
+    }
+}
+
+impl HeadMigration {
+    fn head(&self, path: &PathBuf) -> Result<bool, Box<dyn Error>> {
+        let HeadConfig {
+            path: root,
+            rows,
+            output,
+            common,
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        writer.write_record(reader.headers()?)?;
+        for record in reader.records().take(*rows) {
+            writer.write_record(&record?)?;
+        }
+
+        self.write_to(root, path, output, &content, writer.into_inner()?)
+    }
+}
+
+struct TailMigration {
+    config: TailConfig,
+}
+impl Migration for TailMigration {
+    type ConfigType = TailConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let TailConfig { path, rows, .. } = &self.config;
+        println!(
+            "Keeping the last {} rows in path {}",
+            &rows.to_string().blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.check_renames(path, &files, &self.config.output)?;
+        self.migrate_files(&files, &self.config.common, |file| self.tail(file))
+    }
+}
+
+impl TailMigration {
+    fn tail(&self, path: &PathBuf) -> Result<bool, Box<dyn Error>> {
+        let TailConfig {
+            path: root,
+            rows,
+            output,
+            common,
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        writer.write_record(reader.headers()?)?;
+        let mut kept = VecDeque::with_capacity(*rows);
+        for record in reader.records() {
+            let record = record?;
+            if kept.len() == *rows {
+                kept.pop_front();
+            }
+            if *rows > 0 {
+                kept.push_back(record);
+            }
+        }
+        for record in kept {
+            writer.write_record(&record)?;
+        }
+
+        self.write_to(root, path, output, &content, writer.into_inner()?)
+    }
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+    path: String,
+    sha256: String,
+    rows: u64,
+}
+
+struct ManifestMigration {
+    config: ManifestConfig,
+}
+impl Migration for ManifestMigration {
+    type ConfigType = ManifestConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let ManifestConfig {
+            path,
+            output,
+            common,
+        } = &self.config;
+        println!(
+            "Writing manifest of path {} to {}",
+            &path.blue(),
+            &output.blue()
+        );
+
+        let mut manifest = vec![];
+        for file in self.iter_csv_files(path, common) {
+            let file = file?;
+            manifest.push(self.manifest_entry(&file).with_path(&file)?);
+        }
+        write_atomic(output, serde_json::to_vec_pretty(&manifest)?).with_path(output)?;
+        println!("{} files in manifest", manifest.len());
+        Ok(())
+    }
+}
+
+impl ManifestMigration {
+    fn manifest_entry(&self, path: &PathBuf) -> Result<ManifestEntry, Box<dyn Error>> {
+        let ManifestConfig {
+            path: root, common, ..
+        } = &self.config;
+        let content = fs::read(path)?;
+        let sha256 = Sha256::digest(&content)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let data = String::from_utf8_lossy(&content);
+        let data = common.normalize_records(&data);
+        let (_, data) = common.split_preamble(&data);
+        let mut rows = 0;
+        for record in common.reader(path, data).records() {
+            record?;
+            rows += 1;
+        }
+        // a --path naming a single file keeps just its file name
+        let relative = match path.strip_prefix(root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            Ok(_) => Path::new(path.file_name().unwrap_or_default()),
+            Err(_) => path,
+        };
+        Ok(ManifestEntry {
+            path: relative.to_string_lossy().to_string(),
+            sha256,
+            rows,
+        })
+    }
+}
+
+struct DistinctMigration {
+    config: DistinctConfig,
+}
+impl Migration for DistinctMigration {
+    type ConfigType = DistinctConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let DistinctConfig {
+            path,
+            column,
+            top,
+            dump_distinct,
+            ..
+        } = &self.config;
+        let files = self.iter_csv_files(path, &self.config.common);
+        if let Some(dir) = dump_distinct {
+            println!(
+                "Writing distinct values in path {} to {}",
+                &path.blue(),
+                &dir.blue()
+            );
+            let columns = self.count_columns(files)?;
+            self.dump(dir, columns)?;
+            return Ok(());
+        }
+        let column = column.as_deref().unwrap_or_default();
+        println!(
+            "Counting distinct values of {} in path {}",
+            &column.blue(),
+            &path.blue()
+        );
+
+        let counts = self.count_values(files)?;
+        println!("{} distinct values", counts.len());
+        for (value, count) in self.sorted(counts).iter().take(top.unwrap_or(20)) {
+            println!("{:>10}  {}", count, value);
+        }
+        Ok(())
+    }
+}
+
+impl DistinctMigration {
+    fn count_values(
+        &self,
+        files: impl Iterator<Item = Result<PathBuf, Box<dyn Error>>>,
+    ) -> Result<IndexMap<String, u64>, Box<dyn Error>> {
+        let DistinctConfig { column, common, .. } = &self.config;
+        let column = column.as_deref().unwrap_or_default();
+        let mut counts: IndexMap<String, u64> = IndexMap::new();
+        let mut skipped = 0;
+        for file in files {
+            let file = &file?;
+            let content = common.read(file).with_path(file)?;
+            let (_, data) = common.split_preamble(&content);
+            let mut reader = common.reader(file, data);
+            let headers = reader.headers().with_path(file)?.clone();
+            let Some(index) = common
+                .matching
+                .find_column(&headers, column)
+                .with_path(file)?
+            else {
+                if let FileOutcome::Skipped(reason) =
+                    common.missing_column(column).with_path(file)?
+                {
+                    warn(format!("Skipped {:?}, {}", file, reason));
+                    skipped += 1;
+                }
+                continue;
+            };
+            for record in reader.records().take(common.sample_size()) {
+                let record = record.with_path(file)?;
+                let value = record.get(index).unwrap_or_default();
+                *counts.entry(value.to_string()).or_default() += 1;
+            }
+        }
+        if skipped > 0 && common.strict {
+            return Err(format!("{} files skipped in strict mode", skipped).into());
+        }
+        Ok(counts)
+    }
+
+    /// Value counts per header name, for every column or just --column.
+    fn count_columns(
+        &self,
+        files: impl Iterator<Item = Result<PathBuf, Box<dyn Error>>>,
+    ) -> Result<IndexMap<String, IndexMap<String, u64>>, Box<dyn Error>> {
+        let DistinctConfig { column, common, .. } = &self.config;
+        let mut columns: IndexMap<String, IndexMap<String, u64>> = IndexMap::new();
+        for file in files {
+            let file = &file?;
+            let content = common.read(file).with_path(file)?;
+            let (_, data) = common.split_preamble(&content);
+            let mut reader = common.reader(file, data);
+            let headers = reader.headers().with_path(file)?.clone();
+            let selected: Vec<(usize, &str)> = headers
+                .iter()
+                .enumerate()
+                .filter(|(_, header)| {
+                    column
+                        .as_deref()
+                        .is_none_or(|column| common.matching.matches(header, column))
+                })
+                .collect();
+            for record in reader.records().take(common.sample_size()) {
+                let record = record.with_path(file)?;
+                for &(i, header) in &selected {
+                    let value = record.get(i).unwrap_or_default();
+                    *columns
+                        .entry(header.to_string())
+                        .or_default()
+                        .entry(value.to_string())
+                        .or_default() += 1;
+                }
+            }
+        }
+        Ok(columns)
+    }
+
+    fn dump(
+        &self,
+        dir: &str,
+        columns: IndexMap<String, IndexMap<String, u64>>,
+    ) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(dir).with_path(dir)?;
+        let mut taken = HashSet::new();
+        for (header, counts) in columns {
+            let sanitized: String = header
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || c == '-' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            let sanitized = match sanitized.trim_matches('_') {
+                "" => "column",
+                sanitized => sanitized,
+            };
+            let mut name = sanitized.to_string();
+            let mut n = 1;
+            while !taken.insert(name.to_lowercase()) {
+                n += 1;
+                name = format!("{}_{}", sanitized, n);
+            }
+            let path = Path::new(dir).join(format!("{}.csv", name));
+            let mut writer = csv::Writer::from_writer(vec![]);
+            writer.write_record(["value", "count"])?;
+            let distinct = counts.len();
+            let top = self.config.top.unwrap_or(usize::MAX);
+            for (value, count) in self.sorted(counts).iter().take(top) {
+                writer.write_record([value, &count.to_string()])?;
+            }
+            write_atomic(&path, writer.into_inner()?).with_path(&path)?;
+            println!("{} distinct values of {} in {:?}", distinct, header, path);
+        }
+        Ok(())
+    }
+
+    fn sorted(&self, counts: IndexMap<String, u64>) -> Vec<(String, u64)> {
+        let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+        if self.config.preserve_order {
+            return counts;
+        }
+        match self.config.sort {
+            DistinctSort::Count => counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0))),
+            DistinctSort::Value => counts.sort_by(|a, b| a.0.cmp(&b.0)),
+        }
+        counts
+    }
+}
+
+struct CoalesceMigration {
+    config: CoalesceConfig,
+}
+impl Migration for CoalesceMigration {
+    type ConfigType = CoalesceConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let CoalesceConfig {
+            path, into, from, ..
+        } = &self.config;
+        println!(
+            "Coalescing {} into {} in path {}",
+            &from.join(",").blue(),
+            &into.blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_files(&files, &self.config.common, |file| self.coalesce(file))
+    }
+}
+
+impl CoalesceMigration {
+    fn coalesce(&self, path: &PathBuf) -> Result<FileOutcome, Box<dyn Error>> {
+        let CoalesceConfig {
+            into,
+            from,
+            order,
+            keep_sources,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        // headers
+        let headers = reader.headers()?.clone();
+        let mut sources = vec![];
+        for column in from {
+            match common.matching.find_column(&headers, column)? {
+                Some(index) => sources.push(index),
+                None => return common.missing_column(column),
+            }
+        }
+        let kept: Vec<usize> = (0..headers.len())
+            .filter(|i| *keep_sources || !sources.contains(i))
+            .collect();
+        let index = match order {
+            InsertOrder::Position(order) => usize::try_from(order - 1).unwrap_or(usize::MAX),
+            InsertOrder::Last => kept.len(),
+        };
+        if index > kept.len() {
+            return Err(format!(
+                "Order {} out of range for {:?} with {} columns",
+                order,
+                path,
+                kept.len()
+            )
+            .into());
+        }
+        let mut new_headers: Vec<&str> = kept.iter().map(|&i| &headers[i]).collect();
+        new_headers.insert(index, into);
+        writer.write_record(&new_headers)?;
+
+        // values
+        for record in reader.records().take(common.sample_size()) {
+            let record = record?;
+            let value = sources
+                .iter()
+                .filter_map(|&i| record.get(i))
+                .find(|value| !value.is_empty())
+                .unwrap_or_default();
+            let mut new_record: Vec<&str> = kept.iter().filter_map(|&i| record.get(i)).collect();
+            new_record.insert(index.min(new_record.len()), value);
+            writer.write_record(&new_record)?;
+        }
+
+        Ok(self
+            .write_if_changed(path, &content, writer.into_inner()?)?
+            .into())
+    }
+}
+
+struct PadMigration {
+    config: PadConfig,
+}
+impl Migration for PadMigration {
+    type ConfigType = PadConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let PadConfig {
+            path,
+            column,
+            width,
+            ..
+        } = &self.config;
+        println!(
+            "Padding {} to width {} in path {}",
+            &column.to_string().blue(),
+            &width.to_string().blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_cells(
+            &files,
+            &self.config.common,
+            "padded",
+            self.config.dry_run,
+            |file| self.pad(file),
+        )
+    }
+}
+
+impl PadMigration {
+    fn pad(&self, path: &PathBuf) -> Result<(FileOutcome, usize), Box<dyn Error>> {
+        let PadConfig {
+            column,
+            row_filter,
+            dry_run,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        // headers
+        let headers = reader.headers()?.clone();
+        let indexes = column.find(&headers, &common.matching)?;
+        if indexes.is_empty() {
+            return Ok((common.missing_column(&column.to_string())?, 0));
+        }
+        writer.write_record(&headers)?;
+        let mut gate = RowGate::new(row_filter.as_ref(), &headers, &common.matching)?;
+
+        // values
+        let mut padded = 0;
+        for record in reader.records().take(common.sample_size()) {
+            let record = record?;
+            if !gate.admits(&record) {
+                writer.write_record(&record)?;
+                continue;
+            }
+            let mut new_record: Vec<String> = record.iter().map(String::from).collect();
+            for &index in &indexes {
+                let Some(value) = new_record.get_mut(index) else {
+                    continue;
+                };
+                let new_value = self.pad_value(value);
+                if new_value != *value {
+                    padded += 1;
+                    *value = new_value;
+                }
+            }
+            writer.write_record(&new_record)?;
+        }
+        gate.report();
+
+        let outcome = self.write_unless_dry_run(path, &content, writer.into_inner()?, *dry_run)?;
+        Ok((outcome, padded))
+    }
+
+    fn pad_value(&self, value: &str) -> String {
+        let PadConfig {
+            width, char, side, ..
+        } = &self.config;
+        let len = value.chars().count();
+        if len >= *width {
+            return value.to_string();
+        }
+        let padding = char.to_string().repeat(width - len);
+        match side {
+            PadSide::Left => padding + value,
+            PadSide::Right => value.to_string() + &padding,
+        }
+    }
+}
+
+struct SliceMigration {
+    config: SliceConfig,
+}
+impl Migration for SliceMigration {
+    type ConfigType = SliceConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let SliceConfig {
+            path,
+            column,
+            start,
+            end,
+            ..
+        } = &self.config;
+        let end = end.map_or(String::new(), |end| end.to_string());
+        println!(
+            "Slicing {} to [{}..{}] in path {}",
+            &column.to_string().blue(),
+            &start.to_string().blue(),
+            &end.blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_cells(
+            &files,
+            &self.config.common,
+            "sliced",
+            self.config.dry_run,
+            |file| self.slice(file),
+        )
+    }
+}
+
+impl SliceMigration {
+    fn slice(&self, path: &PathBuf) -> Result<(FileOutcome, usize), Box<dyn Error>> {
+        let SliceConfig {
+            column,
+            row_filter,
+            dry_run,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        // headers
+        let headers = reader.headers()?.clone();
+        let indexes = column.find(&headers, &common.matching)?;
+        if indexes.is_empty() {
+            return Ok((common.missing_column(&column.to_string())?, 0));
+        }
+        writer.write_record(&headers)?;
+        let mut gate = RowGate::new(row_filter.as_ref(), &headers, &common.matching)?;
+
+        // values
+        let mut sliced = 0;
+        for record in reader.records().take(common.sample_size()) {
+            let record = record?;
+            if !gate.admits(&record) {
+                writer.write_record(&record)?;
+                continue;
+            }
+            let mut new_record: Vec<&str> = record.iter().collect();
+            for &index in &indexes {
+                let Some(value) = new_record.get_mut(index) else {
+                    continue;
+                };
+                let new_value = self.slice_value(value);
+                if new_value != *value {
+                    sliced += 1;
+                    *value = new_value;
+                }
+            }
+            writer.write_record(&new_record)?;
+        }
+        gate.report();
+
+        let outcome = self.write_unless_dry_run(path, &content, writer.into_inner()?, *dry_run)?;
+        Ok((outcome, sliced))
+    }
+
+    fn slice_value<'a>(&self, value: &'a str) -> &'a str {
+        let SliceConfig { start, end, .. } = &self.config;
+        let byte_offset = |chars: usize| {
+            value
+                .char_indices()
+                .nth(chars)
+                .map_or(value.len(), |(offset, _)| offset)
+        };
+        let start = byte_offset(*start);
+        let end = end.map_or(value.len(), byte_offset).max(start);
+        &value[start..end]
+    }
+}
+
+struct PipeMigration {
+    config: PipeConfig,
+}
+impl Migration for PipeMigration {
+    type ConfigType = PipeConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let PipeConfig {
+            path,
+            column,
+            command,
+            allow_exec,
+            ..
+        } = &self.config;
+        if !allow_exec {
+            return Err("Pipe runs an external command, pass --allow-exec to confirm".into());
+        }
+        println!(
+            "Piping {} through {} in path {}",
+            &column.blue(),
+            &command.blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_files(&files, &self.config.common, |file| self.pipe(file))
+    }
+}
+
+impl PipeMigration {
+    fn pipe(&self, path: &PathBuf) -> Result<FileOutcome, Box<dyn Error>> {
+        let PipeConfig {
+            column,
+            batch,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        // headers
+        let headers = reader.headers()?.clone();
+        let Some(index) = common.matching.find_column(&headers, column)? else {
+            return common.missing_column(column);
+        };
+        writer.write_record(&headers)?;
+
+        // values
+        let records = reader
+            .records()
+            .take(common.sample_size())
+            .collect::<Result<Vec<_>, _>>()?;
+        let values: Vec<&str> = records
+            .iter()
+            .map(|record| record.get(index).unwrap_or_default())
+            .collect();
+        let new_values = if *batch {
+            self.run_batch(&values)?
+        } else {
+            values
+                .iter()
+                .map(|value| self.run_command(value))
+                .collect::<Result<_, _>>()?
+        };
+        for (record, new_value) in records.iter().zip(new_values) {
+            let mut new_record: Vec<&str> = record.iter().collect();
+            if let Some(value) = new_record.get_mut(index) {
+                *value = &new_value;
+            }
+            writer.write_record(&new_record)?;
+        }
+
+        Ok(self
+            .write_if_changed(path, &content, writer.into_inner()?)?
+            .into())
+    }
+
+    fn run_command(&self, input: &str) -> Result<String, Box<dyn Error>> {
+        let command = &self.config.command;
+        let mut shell = if cfg!(windows) {
+            let mut shell = process::Command::new("cmd");
+            shell.args(["/C", command]);
+            shell
+        } else {
+            let mut shell = process::Command::new("sh");
+            shell.args(["-c", command]);
+            shell
+        };
+        let mut child = shell.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let mut stdin = child.stdin.take().ok_or("Command stdin not captured")?;
+        let input = input.to_string();
+        // feed stdin from a thread so a command filling its stdout can't deadlock
+        let feeder = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output()?;
+        feeder
+            .join()
+            .map_err(|_| "Command stdin writer panicked")??;
+        if !output.status.success() {
+            return Err(format!("Command {} failed with {}", command, output.status).into());
+        }
+        let output = String::from_utf8(output.stdout)?;
+        let output = output.strip_suffix('\n').unwrap_or(&output);
+        Ok(output.strip_suffix('\r').unwrap_or(output).to_string())
+    }
+
+    fn run_batch(&self, values: &[&str]) -> Result<Vec<String>, Box<dyn Error>> {
+        if values.iter().any(|value| value.contains(['\r', '\n'])) {
+            return Err("Values with line breaks can't be piped in batch mode".into());
+        }
+        if values.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut input = values.join("\n");
+        input.push('\n');
+        let output = self.run_command(&input)?;
+        let new_values: Vec<String> = output
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+            .collect();
+        if new_values.len() != values.len() {
+            return Err(format!(
+                "Command {} returned {} lines for {} values",
+                self.config.command,
+                new_values.len(),
+                values.len()
+            )
+            .into());
+        }
+        Ok(new_values)
+    }
+}
+
+struct FromJsonMigration {
+    config: FromJsonConfig,
+    common: CommonConfig,
+}
+impl Migration for FromJsonMigration {
+    type ConfigType = FromJsonConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        let common = CommonConfig {
+            extensions: config.extensions.clone(),
+            delimiter: config.delimiter,
+            ..CommonConfig::default()
+        };
+        Self { config, common }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let FromJsonConfig {
+            path, output_dir, ..
+        } = &self.config;
+        println!(
+            "Converting NDJSON in path {} to CSV in {}",
+            &path.blue(),
+            &output_dir.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.common)?;
+        self.migrate_files(&files, &self.common, |file| self.convert_file(file))
+    }
+}
+
+impl FromJsonMigration {
+    fn convert_file(&self, path: &PathBuf) -> Result<bool, Box<dyn Error>> {
+        let FromJsonConfig {
+            path: root,
+            output_dir,
+            columns,
+            ..
+        } = &self.config;
+        let content = fs::read_to_string(path)?;
+        let mut objects = vec![];
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line)? {
+                serde_json::Value::Object(object) => objects.push(object),
+                _ => return Err(format!("Line {} is not a JSON object", i + 1).into()),
+            }
+        }
+        let columns = match (columns, objects.first()) {
+            (Some(columns), _) => columns.clone(),
+            (None, Some(object)) => object.keys().cloned().collect(),
+            (None, None) => vec![],
+        };
+
+        let mut destination = self.output_path(root, path, &Some(output_dir.clone()))?;
+        destination.set_extension("csv");
+        let mut writer = self.common.writer(&destination, "");
+        writer.write_record(&columns)?;
+        for object in &objects {
+            writer.write_record(columns.iter().map(|column| match object.get(column) {
+                None | Some(serde_json::Value::Null) => String::new(),
+                Some(serde_json::Value::String(value)) => value.clone(),
+                Some(value) => value.to_string(),
+            }))?;
+        }
+        self.write_output(path, &destination, "", writer.into_inner()?)
+    }
+}
+
+struct ToFixedMigration {
+    config: ToFixedConfig,
+}
+impl Migration for ToFixedMigration {
+    type ConfigType = ToFixedConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let ToFixedConfig {
+            path, output_dir, ..
+        } = &self.config;
+        println!(
+            "Writing fixed-width text of path {} to {}",
+            &path.blue(),
+            &output_dir.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_files(&files, &self.config.common, |file| self.to_fixed(file))
+    }
+}
+
+impl ToFixedMigration {
+    fn to_fixed(&self, path: &PathBuf) -> Result<FileOutcome, Box<dyn Error>> {
+        let ToFixedConfig {
+            path: root,
+            widths,
+            output_dir,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (_, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+
+        let headers = reader.headers()?.clone();
+        if headers.len() != widths.len() {
+            return Ok(FileOutcome::Skipped(format!(
+                "{} widths for {} columns",
+                widths.len(),
+                headers.len()
+            )));
+        }
+        let mut output = String::new();
+        for (row, record) in reader.records().take(common.sample_size()).enumerate() {
+            let record = record?;
+            for (i, width) in widths.iter().enumerate() {
+                let value = record.get(i).unwrap_or_default();
+                output += &self.fixed_value(value, *width).ok_or_else(|| {
+                    format!(
+                        "Value {:?} in row {} is wider than {} for column {}",
+                        value,
+                        row + 1,
+                        width,
+                        &headers[i]
+                    )
+                })?;
+            }
+            output.push('\n');
+        }
+
+        let mut destination = self.output_path(root, path, &Some(output_dir.clone()))?;
+        destination.set_extension("txt");
+        Ok(self
+            .write_output(path, &destination, "", output.into_bytes())?
+            .into())
+    }
+
+    fn fixed_value(&self, value: &str, width: usize) -> Option<String> {
+        let len = value.chars().count();
+        if len > width {
+            return self
+                .config
+                .truncate
+                .then(|| value.chars().take(width).collect());
+        }
+        let padding = " ".repeat(width - len);
+        Some(match self.config.align {
+            FixedAlign::Left => value.to_string() + &padding,
+            FixedAlign::Right => padding + value,
+        })
+    }
+}
+
+struct TransposeMigration {
+    config: TransposeConfig,
+}
+impl Migration for TransposeMigration {
+    type ConfigType = TransposeConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let TransposeConfig { path, output } = &self.config;
+        println!("Transposing {} into {}", &path.blue(), &output.blue());
+
+        if Path::new(path).is_dir() {
+            return Err(format!("Transpose takes a single file, {} is a directory", path).into());
+        }
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(path)
+            .with_path(path)?;
+        let rows = reader
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .with_path(path)?;
+        let width = rows.iter().map(StringRecord::len).max().unwrap_or_default();
+
+        // the input is fully read, so --output may name the same file
+        let mut writer = csv::Writer::from_writer(vec![]);
+        for i in 0..width {
+            writer.write_record(rows.iter().map(|row| row.get(i).unwrap_or_default()))?;
+        }
+        write_atomic(output, writer.into_inner()?).with_path(output)?;
+        println!("{} rows transposed into {} rows", rows.len(), width);
+        Ok(())
+    }
+}
+
+struct MeltMigration {
+    config: MeltConfig,
+}
+impl Migration for MeltMigration {
+    type ConfigType = MeltConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let MeltConfig {
+            path,
+            value_columns,
+            ..
+        } = &self.config;
+        println!(
+            "Melting {} into rows in path {}",
+            &value_columns.join(",").blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.check_renames(path, &files, &self.config.output)?;
+        self.migrate_files(&files, &self.config.common, |file| self.melt(file))
+    }
+}
+
+impl MeltMigration {
+    fn melt(&self, path: &PathBuf) -> Result<FileOutcome, Box<dyn Error>> {
+        let MeltConfig {
+            path: root,
+            id_columns,
+            value_columns,
+            var_name,
+            value_name,
+            output,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        // headers
+        let headers = reader.headers()?.clone();
+        let mut ids = vec![];
+        let mut values = vec![];
+        for (columns, indexes) in [(id_columns, &mut ids), (value_columns, &mut values)] {
+            for column in columns {
+                match common.matching.find_column(&headers, column)? {
+                    Some(index) => indexes.push(index),
+                    None => return common.missing_column(column),
+                }
+            }
+        }
+        let mut new_headers: Vec<&str> = ids.iter().map(|&i| &headers[i]).collect();
+        new_headers.extend([var_name.as_str(), value_name.as_str()]);
+        writer.write_record(&new_headers)?;
+
+        // values
+        for record in reader.records().take(common.sample_size()) {
+            let record = record?;
+            for &value in &values {
+                let mut new_record: Vec<&str> = ids
+                    .iter()
+                    .map(|&i| record.get(i).unwrap_or_default())
+                    .collect();
+                new_record.extend([&headers[value], record.get(value).unwrap_or_default()]);
+                writer.write_record(&new_record)?;
+            }
+        }
+
+        Ok(self
+            .write_to(root, path, output, &content, writer.into_inner()?)?
+            .into())
+    }
+}
+
+struct PivotMigration {
+    config: PivotConfig,
+}
+impl Migration for PivotMigration {
+    type ConfigType = PivotConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let PivotConfig {
+            path,
+            column,
+            value,
+            ..
+        } = &self.config;
+        println!(
+            "Pivoting {} by {} in path {}",
+            &value.blue(),
+            &column.blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.check_renames(path, &files, &self.config.output)?;
+        self.migrate_files(&files, &self.config.common, |file| self.pivot(file))
+    }
+}
+
+impl PivotMigration {
+    fn pivot(&self, path: &PathBuf) -> Result<FileOutcome, Box<dyn Error>> {
+        let PivotConfig {
+            path: root,
+            index,
+            column,
+            value,
+            aggregate,
+            output,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        // headers
+        let headers = reader.headers()?.clone();
+        let mut indexes = vec![];
+        for name in index.iter().chain([column, value]) {
+            match common.matching.find_column(&headers, name)? {
+                Some(i) => indexes.push(i),
+                None => return common.missing_column(name),
+            }
+        }
+        let value_index = indexes.pop().unwrap_or_default();
+        let column_index = indexes.pop().unwrap_or_default();
+
+        // values
+        let mut new_columns: IndexSet<String> = IndexSet::new();
+        let mut groups: IndexMap<Vec<String>, HashMap<String, String>> = IndexMap::new();
+        for record in reader.records().take(common.sample_size()) {
+            let record = record?;
+            let field = |i: usize| record.get(i).unwrap_or_default().to_string();
+            let key: Vec<String> = indexes.iter().map(|&i| field(i)).collect();
+            let new_column = field(column_index);
+            let cells = groups.entry(key.clone()).or_default();
+            if cells.contains_key(&new_column) {
+                match aggregate {
+                    PivotAggregate::First => continue,
+                    PivotAggregate::Last => {}
+                    PivotAggregate::Error => {
+                        return Err(format!(
+                            "Duplicate value for {} {} in group {}",
+                            column,
+                            new_column,
+                            key.join(",")
+                        )
+                        .into())
+                    }
+                }
+            }
+            cells.insert(new_column.clone(), field(value_index));
+            new_columns.insert(new_column);
+        }
+
+        let mut new_headers: Vec<&str> = indexes.iter().map(|&i| &headers[i]).collect();
+        new_headers.extend(new_columns.iter().map(String::as_str));
+        writer.write_record(&new_headers)?;
+        for (key, cells) in &groups {
+            let mut new_record: Vec<&str> = key.iter().map(String::as_str).collect();
+            new_record.extend(
+                new_columns
+                    .iter()
+                    .map(|c| cells.get(c).map_or("", String::as_str)),
+            );
+            writer.write_record(&new_record)?;
+        }
+
+        Ok(self
+            .write_to(root, path, output, &content, writer.into_inner()?)?
+            .into())
+    }
+}
+
+struct ReplaceHeadersMigration {
+    config: ReplaceHeadersConfig,
+}
+impl Migration for ReplaceHeadersMigration {
+    type ConfigType = ReplaceHeadersConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let ReplaceHeadersConfig {
+            path,
+            find,
+            replace,
+            regex,
+            ..
+        } = &self.config;
+        println!(
+            "Replacing {} with {} in headers in path {}",
+            &find.blue(),
+            &replace.blue(),
+            &path.blue()
+        );
+
+        let regex = if *regex {
+            Some(Regex::new(find)?)
+        } else {
+            None
+        };
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_files(&files, &self.config.common, |file| {
+            self.replace_headers(file, regex.as_ref())
+        })
+    }
+}
+
+impl ReplaceHeadersMigration {
+    fn replace_headers(
+        &self,
+        path: &PathBuf,
+        regex: Option<&Regex>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let ReplaceHeadersConfig {
+            find,
+            replace,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        let headers = reader.headers()?.clone();
+        let new_headers: Vec<String> = headers
+            .iter()
+            .map(|header| match regex {
+                Some(regex) => regex.replace_all(header, replace.as_str()).into_owned(),
+                None => header.replace(find.as_str(), replace),
+            })
+            .collect();
+        let mut renamed = 0;
+        for (header, new_header) in headers.iter().zip(&new_headers) {
+            if header != new_header {
+                println!("{:?} -> {:?}", header, new_header);
+                renamed += 1;
+            }
+        }
+        if renamed == 0 && !common.drops_preamble(preamble) {
+            return Ok(false);
+        }
+        writer.write_record(&new_headers)?;
+
+        let output = self.copy_data_rows(data, &reader, writer)?;
+        self.write_if_changed(path, &content, output)
+    }
+}
+
+struct DedupeColumnsMigration {
+    config: DedupeColumnsConfig,
+}
+impl Migration for DedupeColumnsMigration {
+    type ConfigType = DedupeColumnsConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let DedupeColumnsConfig { path, by_name, .. } = &self.config;
+        println!(
+            "Dropping duplicate columns{} in path {}",
+            if *by_name {
+                " with matching headers"
+            } else {
+                ""
+            },
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_files(&files, &self.config.common, |file| self.dedupe(file))
+    }
+}
+
+impl DedupeColumnsMigration {
+    fn dedupe(&self, path: &PathBuf) -> Result<bool, Box<dyn Error>> {
+        let DedupeColumnsConfig {
+            by_name, common, ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        let headers = reader.headers()?.clone();
+        let rows = reader
+            .records()
+            .take(common.sample_size())
+            .collect::<Result<Vec<_>, _>>()?;
+        let truncated = reader.records().next().is_some();
+        let column = |i: usize| rows.iter().map(move |row| row.get(i));
+
+        // keep a column unless an earlier kept column holds the same values;
+        // without data rows there are no values to compare
+        let mut kept: Vec<usize> = vec![];
+        for i in 0..headers.len() {
+            let original = kept.iter().find(|&&j| {
+                !rows.is_empty()
+                    && (!by_name || headers[i] == headers[j])
+                    && column(i).eq(column(j))
+            });
+            match original {
+                Some(&j) => println!("Dropping {} (copy of {})", &headers[i], &headers[j]),
+                None => kept.push(i),
+            }
+        }
+        if kept.len() == headers.len() && !truncated && !common.drops_preamble(preamble) {
+            return Ok(false);
+        }
+
+        writer.write_record(kept.iter().map(|&i| &headers[i]))?;
+        for row in &rows {
+            writer.write_record(kept.iter().filter_map(|&i| row.get(i)))?;
+        }
+        self.write_if_changed(path, &content, writer.into_inner()?)
+    }
+}
+
+struct NormalizeHeadersMigration {
+    config: NormalizeConfig,
+}
+impl Migration for NormalizeHeadersMigration {
+    type ConfigType = NormalizeConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let NormalizeConfig { path, .. } = &self.config;
+        println!("Normalizing headers in path {}", &path.blue());
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_files(&files, &self.config.common, |file| self.normalize(file))
+    }
+}
+
+impl NormalizeHeadersMigration {
+    fn normalize(&self, path: &PathBuf) -> Result<bool, Box<dyn Error>> {
+        let NormalizeConfig { common, .. } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        let headers = reader.headers()?.clone();
+        let new_headers = self.normalized_headers(&headers);
+        let renamed = headers.iter().ne(new_headers.iter().map(String::as_str));
+        if renamed {
+            println!("Before: {}", headers.iter().collect::<Vec<_>>().join(","));
+            println!("After:  {}", new_headers.join(","));
+        } else if !common.drops_preamble(preamble) {
+            return Ok(false);
+        }
+        writer.write_record(&new_headers)?;
+
+        let output = self.copy_data_rows(data, &reader, writer)?;
+        self.write_if_changed(path, &content, output)
+    }
+
+    fn normalized_headers(&self, headers: &StringRecord) -> Vec<String> {
+        let normalized: Vec<String> = headers
+            .iter()
+            .map(|header| {
+                let header = header.split_whitespace().collect::<Vec<_>>().join(" ");
+                if self.config.lower {
+                    header.to_lowercase()
+                } else {
+                    header
+                }
+            })
+            .collect();
+        // names normalized earlier in the row keep priority, even over a
+        // later header that already carries a suffix
+        let mut taken: HashSet<String> = HashSet::new();
+        normalized
+            .iter()
+            .map(|header| {
+                let mut name = header.clone();
+                let mut n = 1;
+                while !taken.insert(name.clone()) {
+                    n += 1;
+                    name = format!("{}_{}", header, n);
+                }
+                name
+            })
+            .collect()
+    }
+}
+
+struct AssertMigration {
+    config: AssertConfig,
+}
+impl Migration for AssertMigration {
+    type ConfigType = AssertConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let AssertConfig {
+            path,
+            column,
+            pattern,
+            ..
+        } = &self.config;
+        println!(
+            "Asserting {} matches {} in path {}",
+            &column.blue(),
+            &pattern.blue(),
+            &path.blue()
+        );
+
+        let pattern = Regex::new(pattern)?;
+        let files = self.iter_csv_files(path, &self.config.common);
+        let failures = self.check_values(files, &pattern)?;
+        if failures > 0 {
+            return Err(format!("{} values don't match {}", failures, pattern).into());
+        }
+        println!("{}", "All values match".green());
+        Ok(())
+    }
+}
+
+impl AssertMigration {
+    fn check_values(
+        &self,
+        files: impl Iterator<Item = Result<PathBuf, Box<dyn Error>>>,
+        pattern: &Regex,
+    ) -> Result<usize, Box<dyn Error>> {
+        let AssertConfig {
+            column,
+            allow_empty,
+            common,
+            ..
+        } = &self.config;
+        let mut failures = 0;
+        let mut skipped = 0;
+        for file in files {
+            let file = &file?;
+            let content = common.read(file).with_path(file)?;
+            let (_, data) = common.split_preamble(&content);
+            let mut reader = common.reader(file, data);
+            let headers = reader.headers().with_path(file)?.clone();
+            let Some(index) = common
+                .matching
+                .find_column(&headers, column)
+                .with_path(file)?
+            else {
+                if let FileOutcome::Skipped(reason) =
+                    common.missing_column(column).with_path(file)?
+                {
+                    warn(format!("Skipped {:?}, {}", file, reason));
+                    skipped += 1;
+                }
+                continue;
+            };
+            for (i, record) in reader.records().take(common.sample_size()).enumerate() {
+                let record = record.with_path(file)?;
+                let value = record.get(index).unwrap_or_default();
+                if (value.is_empty() && *allow_empty) || pattern.is_match(value) {
+                    continue;
+                }
+                println!("{}", format!("{:?} row {}: {:?}", file, i + 1, value).red());
+                failures += 1;
+            }
+        }
+        if skipped > 0 && common.strict {
+            return Err(format!("{} files skipped in strict mode", skipped).into());
+        }
+        Ok(failures)
+    }
+}
+
+struct ClampMigration {
+    config: ClampConfig,
+}
+impl Migration for ClampMigration {
+    type ConfigType = ClampConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let ClampConfig {
+            path,
+            column,
+            min,
+            max,
+            ..
+        } = &self.config;
+        let bound = |bound: &Option<f64>| bound.map(|b| b.to_string()).unwrap_or_default();
+        println!(
+            "Clamping {} into [{}, {}] in path {}",
+            &column.to_string().blue(),
+            &bound(min).blue(),
+            &bound(max).blue(),
+            &path.blue()
+        );
+
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                return Err(format!("--min {} is greater than --max {}", min, max).into());
+            }
+        }
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_cells(
+            &files,
+            &self.config.common,
+            "clamped",
+            self.config.dry_run,
+            |file| self.clamp(file),
+        )
+    }
+}
+
+impl ClampMigration {
+    fn clamp(&self, path: &PathBuf) -> Result<(FileOutcome, usize), Box<dyn Error>> {
+        let ClampConfig {
+            column,
+            row_filter,
+            dry_run,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        // headers
+        let headers = reader.headers()?.clone();
+        let indexes = column.find(&headers, &common.matching)?;
+        if indexes.is_empty() {
+            return Ok((common.missing_column(&column.to_string())?, 0));
+        }
+        writer.write_record(&headers)?;
+        let mut gate = RowGate::new(row_filter.as_ref(), &headers, &common.matching)?;
+
+        // values
+        let mut clamped = 0;
+        for (i, record) in reader.records().take(common.sample_size()).enumerate() {
+            let record = record?;
+            if !gate.admits(&record) {
+                writer.write_record(&record)?;
+                continue;
+            }
+            let mut new_record: Vec<String> = record.iter().map(String::from).collect();
+            for &index in &indexes {
+                let Some(value) = new_record.get_mut(index) else {
+                    continue;
+                };
+                let new_value = self
+                    .clamp_value(value)
+                    .map_err(|e| format!("row {}: {}", i + 1, e))?;
+                if new_value != *value {
+                    clamped += 1;
+                    *value = new_value;
+                }
+            }
+            writer.write_record(&new_record)?;
+        }
+        gate.report();
+
+        let outcome = self.write_unless_dry_run(path, &content, writer.into_inner()?, *dry_run)?;
+        Ok((outcome, clamped))
+    }
+
+    fn clamp_value(&self, value: &str) -> Result<String, String> {
+        let ClampConfig {
+            min, max, on_error, ..
+        } = &self.config;
+        if value.trim().is_empty() {
+            return Ok(value.to_string());
+        }
+        let Ok(number) = value.trim().parse::<f64>() else {
+            return match on_error {
+                ClampOnError::Keep => Ok(value.to_string()),
+                ClampOnError::Clear => Ok(String::new()),
+                ClampOnError::Error => Err(format!("{:?} is not a number", value)),
+            };
+        };
+        Ok(match (min, max) {
+            (Some(min), _) if number < *min => min.to_string(),
+            (_, Some(max)) if number > *max => max.to_string(),
+            _ => value.to_string(),
+        })
+    }
+}
+
+struct SelfCheckMigration {
+    config: SelfCheckConfig,
+}
+impl Migration for SelfCheckMigration {
+    type ConfigType = SelfCheckConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let SelfCheckConfig { path, common } = &self.config;
+        println!("Checking round trips in path {}", &path.blue());
+
+        let mut differing = 0;
+        for file in self.iter_csv_files(path, common) {
+            let file = &file?;
+            if let Some(difference) = self.round_trip(file).with_path(file)? {
+                println!("{}", format!("{:?}: {}", file, difference).red());
+                differing += 1;
+            }
+        }
+        if differing > 0 {
+            return Err(format!("{} files don't round-trip unchanged", differing).into());
+        }
+        println!("{}", "All files round-trip unchanged".green());
+        Ok(())
+    }
+}
+
+impl SelfCheckMigration {
+    /// What an identity migration would change in the file, if anything.
+    fn round_trip(&self, path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+        let common = &self.config.common;
+        let content = fs::read_to_string(path)?;
+        let normalized = common.normalize_records(&content);
+        let (preamble, data) = common.split_preamble(&normalized);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+        writer.write_record(reader.headers()?)?;
+        for record in reader.records() {
+            writer.write_record(&record?)?;
+        }
+        let output = String::from_utf8(writer.into_inner()?)?;
+        Ok(round_trip_difference(&content, &output, common))
+    }
+}
+
+fn round_trip_difference(original: &str, output: &str, common: &CommonConfig) -> Option<String> {
+    if original == output {
+        return None;
+    }
+    let (preamble, _) = common.split_preamble(original);
+    if common.drops_preamble(preamble) {
+        return Some("the preamble is dropped, see --keep-preamble".to_string());
+    }
+    if original.starts_with('\u{feff}') && !output.starts_with('\u{feff}') {
+        return Some("the byte order mark is dropped".to_string());
+    }
+    if original.contains('\r') && !output.contains('\r') {
+        return Some("CR line terminators become LF".to_string());
+    }
+    if output.strip_suffix('\n') == Some(original) {
+        return Some("a trailing newline is added".to_string());
+    }
+    let line = original
+        .lines()
+        .zip(output.lines())
+        .position(|(a, b)| a != b)
+        .unwrap_or(original.lines().count().min(output.lines().count()));
+    Some(format!("quoting or fields differ from line {}", line + 1))
+}
+
+struct RangeMigration {
+    config: RangeConfig,
+}
+impl Migration for RangeMigration {
+    type ConfigType = RangeConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let RangeConfig {
+            path, rows_range, ..
+        } = &self.config;
+        println!(
+            "Keeping rows {} in path {}",
+            &rows_range.to_string().blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.check_renames(path, &files, &self.config.output)?;
+        self.migrate_files(&files, &self.config.common, |file| self.range(file))
+    }
+}
+
+impl RangeMigration {
+    fn range(&self, path: &PathBuf) -> Result<bool, Box<dyn Error>> {
+        let RangeConfig {
+            path: root,
+            rows_range,
+            output,
+            common,
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        writer.write_record(reader.headers()?)?;
+        let mut kept = 0;
+        let rows = reader.records().skip(rows_range.start - 1);
+        for record in rows.take(rows_range.end - rows_range.start + 1) {
+            writer.write_record(&record?)?;
+            kept += 1;
+        }
+        println!("{} rows kept", kept);
+
+        self.write_to(root, path, output, &content, writer.into_inner()?)
+    }
+}
+
+struct AnonymizeMigration {
+    config: AnonConfig,
+}
+impl Migration for AnonymizeMigration {
+    type ConfigType = AnonConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let AnonConfig {
+            path,
+            columns,
+            mode,
+            ..
+        } = &self.config;
+        let action = match mode {
+            AnonMode::Fake => "faked",
+            AnonMode::Shuffle => "shuffled",
+        };
+        println!(
+            "Obfuscating {} by {} values in path {}",
+            &columns.join(",").blue(),
+            action,
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_cells(&files, &self.config.common, action, false, |file| {
+            self.anonymize(file)
+        })
+    }
+}
+
+impl AnonymizeMigration {
+    fn anonymize(&self, path: &PathBuf) -> Result<(FileOutcome, usize), Box<dyn Error>> {
+        let AnonConfig {
+            columns,
+            mode,
+            seed,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        // headers
+        let headers = reader.headers()?.clone();
+        let mut indexes = vec![];
+        for column in columns {
+            match common.matching.find_column(&headers, column)? {
+                Some(index) => indexes.push(index),
+                None => return Ok((common.missing_column(column)?, 0)),
+            }
+        }
+        writer.write_record(&headers)?;
+
+        // values
+        let mut rows: Vec<Vec<String>> = reader
+            .records()
+            .take(common.sample_size())
+            .map(|record| Ok(record?.iter().map(String::from).collect()))
+            .collect::<Result<_, csv::Error>>()?;
+        let mut changed = 0;
+        for &index in &indexes {
+            let originals: Vec<Option<String>> =
+                rows.iter().map(|row| row.get(index).cloned()).collect();
+            match mode {
+                AnonMode::Fake => {
+                    for value in rows.iter_mut().filter_map(|row| row.get_mut(index)) {
+                        *value = fake_value(*seed, &headers[index], value);
+                    }
+                }
+                AnonMode::Shuffle => {
+                    let mut values: Vec<String> = originals.iter().flatten().cloned().collect();
+                    shuffle(&mut values, column_seed(*seed, &headers[index]));
+                    let mut values = values.into_iter();
+                    for value in rows.iter_mut().filter_map(|row| row.get_mut(index)) {
+                        *value = values.next().unwrap_or_default();
+                    }
+                }
+            }
+            changed += rows
+                .iter()
+                .zip(&originals)
+                .filter(|(row, original)| row.get(index) != original.as_ref())
+                .count();
+        }
+        for row in &rows {
+            writer.write_record(row)?;
+        }
+
+        let output = writer.into_inner()?;
+        Ok((
+            self.write_if_changed(path, &content, output)?.into(),
+            changed,
+        ))
+    }
+}
+
+/// A fake for `value` with the same length and character classes, derived
+/// from a hash so equal values get equal fakes across rows and files.
+fn fake_value(seed: u64, column: &str, value: &str) -> String {
+    let mut stream = vec![];
+    let mut block = 0u64;
+    let mut fake = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        if i >= stream.len() {
+            let mut hasher = Sha256::new();
+            hasher.update(seed.to_le_bytes());
+            hasher.update(block.to_le_bytes());
+            hasher.update(column.as_bytes());
+            hasher.update([0]);
+            hasher.update(value.as_bytes());
+            stream.extend_from_slice(&hasher.finalize());
+            block += 1;
+        }
+        let byte = stream[i];
+        fake.push(match c {
+            '0'..='9' => char::from(b'0' + byte % 10),
+            c if c.is_alphabetic() && c.is_uppercase() => char::from(b'A' + byte % 26),
+            c if c.is_alphabetic() => char::from(b'a' + byte % 26),
+            c => c,
+        });
+    }
+    fake
+}
+
+/// A seed of its own for each column, so shuffled columns don't all move
+/// their values the same way and keep rows together.
+fn column_seed(seed: u64, column: &str) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(seed.to_le_bytes());
+    hasher.update(column.as_bytes());
+    let digest = hasher.finalize();
+    u64::from_le_bytes(digest[..8].try_into().unwrap())
+}
+
+/// Seeded Fisher-Yates shuffle, using splitmix64 so the order only depends
+/// on the seed and the number of values.
+fn shuffle<T>(values: &mut [T], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    for i in (1..values.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        values.swap(i, j);
+    }
+}
+
+struct DeaccentMigration {
+    config: DeaccentConfig,
+}
+impl Migration for DeaccentMigration {
+    type ConfigType = DeaccentConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let DeaccentConfig { path, column, .. } = &self.config;
+        match column {
+            Some(column) => println!(
+                "Stripping accents from {} in path {}",
+                &column.blue(),
+                &path.blue()
+            ),
+            None => println!("Stripping accents in path {}", &path.blue()),
+        }
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_cells(
+            &files,
+            &self.config.common,
+            "deaccented",
+            self.config.dry_run,
+            |file| self.deaccent(file),
+        )
+    }
+}
+
+impl DeaccentMigration {
+    fn deaccent(&self, path: &PathBuf) -> Result<(FileOutcome, usize), Box<dyn Error>> {
+        let DeaccentConfig {
+            column,
+            dry_run,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+        let mut deaccented = 0;
+        let mut deaccent = |value: &str| {
+            let new_value: String = value
+                .nfd()
+                .filter(|c| !is_combining_mark(*c))
+                .nfc()
+                .collect();
+            if new_value != value {
+                deaccented += 1;
+            }
+            new_value
+        };
+
+        // headers
+        let headers = reader.headers()?.clone();
+        let index = match column {
+            Some(column) => match common.matching.find_column(&headers, column)? {
+                Some(index) => Some(index),
+                None => return Ok((common.missing_column(column)?, 0)),
+            },
+            None => None,
+        };
+        match index {
+            Some(_) => writer.write_record(&headers)?,
+            None => writer.write_record(headers.iter().map(&mut deaccent))?,
+        }
+
+        // values
+        for record in reader.records().take(common.sample_size()) {
+            let record = record?;
+            let new_record: Vec<String> = record
+                .iter()
+                .enumerate()
+                .map(|(i, value)| match index {
+                    Some(index) if i != index => value.to_string(),
+                    _ => deaccent(value),
+                })
+                .collect();
+            writer.write_record(&new_record)?;
+        }
+
+        let outcome = self.write_unless_dry_run(path, &content, writer.into_inner()?, *dry_run)?;
+        Ok((outcome, deaccented))
+    }
+}
+
+struct RenameManyMigration {
+    config: RenameManyConfig,
+}
+impl Migration for RenameManyMigration {
+    type ConfigType = RenameManyConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let RenameManyConfig { path, map, .. } = &self.config;
+        let renames = self.read_map().with_path(map)?;
+        println!(
+            "Renaming {} headers from {} in path {}",
+            &renames.len().to_string().blue(),
+            &map.blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_files(&files, &self.config.common, |file| {
+            self.rename(file, &renames)
+        })
+    }
+}
+
+impl RenameManyMigration {
+    fn read_map(&self) -> Result<IndexMap<String, String>, Box<dyn Error>> {
+        let map = &self.config.map;
+        if Path::new(map)
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+        {
+            let object: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_str(&fs::read_to_string(map)?)?;
+            return object
+                .into_iter()
+                .map(|(old, new)| match new {
+                    serde_json::Value::String(new) => Ok((old, new)),
+                    _ => Err(format!("New name for {} is not a string", old).into()),
+                })
+                .collect();
+        }
+        let mut renames = IndexMap::new();
+        for record in csv::Reader::from_path(map)?.records() {
+            let record = record?;
+            match (record.get(0), record.get(1)) {
+                (Some(old), Some(new)) => renames.insert(old.to_string(), new.to_string()),
+                _ => return Err(format!("Expected old and new names, got {:?}", record).into()),
+            };
+        }
+        Ok(renames)
+    }
+
+    fn rename(
+        &self,
+        path: &PathBuf,
+        renames: &IndexMap<String, String>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let common = &self.config.common;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        let headers = reader.headers()?.clone();
+        let mut new_headers: Vec<&str> = headers.iter().collect();
+        let mut not_found = vec![];
+        for (old, new) in renames {
+            match common.matching.find_column(&headers, old)? {
+                Some(index) => new_headers[index] = new,
+                None => not_found.push(old.as_str()),
+            }
+        }
+        if !not_found.is_empty() {
+            println!(
+                "{}",
+                format!("Not found: {}", not_found.join(", ")).yellow()
+            );
+        }
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = new_headers.iter().find(|header| !seen.insert(**header)) {
+            return Err(format!("Renaming would leave two {} columns", duplicate).into());
+        }
+        if headers.iter().eq(new_headers.iter().copied()) && !common.drops_preamble(preamble) {
+            return Ok(false);
+        }
+        writer.write_record(&new_headers)?;
+
+        let output = self.copy_data_rows(data, &reader, writer)?;
+        self.write_if_changed(path, &content, output)
+    }
+}
+
+struct RoundMigration {
+    config: RoundConfig,
+}
+impl Migration for RoundMigration {
+    type ConfigType = RoundConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let RoundConfig {
+            path,
+            column,
+            decimals,
+            ..
+        } = &self.config;
+        println!(
+            "Rounding {} to {} decimals in path {}",
+            &column.to_string().blue(),
+            &decimals.to_string().blue(),
+            &path.blue()
+        );
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_cells(
+            &files,
+            &self.config.common,
+            "rounded",
+            self.config.dry_run,
+            |file| self.round(file),
+        )
+    }
+}
+
+impl RoundMigration {
+    fn round(&self, path: &PathBuf) -> Result<(FileOutcome, usize), Box<dyn Error>> {
+        let RoundConfig {
+            column,
+            row_filter,
+            dry_run,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        // headers
+        let headers = reader.headers()?.clone();
+        let indexes = column.find(&headers, &common.matching)?;
+        if indexes.is_empty() {
+            return Ok((common.missing_column(&column.to_string())?, 0));
+        }
+        writer.write_record(&headers)?;
+        let mut gate = RowGate::new(row_filter.as_ref(), &headers, &common.matching)?;
+
+        // values
+        let mut rounded = 0;
+        for record in reader.records().take(common.sample_size()) {
+            let record = record?;
+            if !gate.admits(&record) {
+                writer.write_record(&record)?;
+                continue;
+            }
+            let mut new_record: Vec<String> = record.iter().map(String::from).collect();
+            for &index in &indexes {
+                let Some(value) = new_record.get_mut(index) else {
+                    continue;
+                };
+                let new_value = self.round_value(value);
+                if new_value != *value {
+                    rounded += 1;
+                    *value = new_value;
+                }
+            }
+            writer.write_record(&new_record)?;
+        }
+        gate.report();
+
+        let outcome = self.write_unless_dry_run(path, &content, writer.into_inner()?, *dry_run)?;
+        Ok((outcome, rounded))
+    }
+
+    fn round_value(&self, value: &str) -> String {
+        let decimals = self.config.decimals;
+        match value.trim().parse::<f64>() {
+            Ok(number) if number.is_finite() => {
+                let rounded = format!("{:.*}", decimals, number);
+                // -0.001 rounds to 0, not -0
+                match rounded.strip_prefix('-') {
+                    Some(unsigned) if unsigned.bytes().all(|b| b == b'0' || b == b'.') => {
+                        unsigned.to_string()
+                    }
+                    _ => rounded,
+                }
+            }
+            _ => value.to_string(),
+        }
+    }
+}
+
+struct NameColumnsMigration {
+    config: NameColumnsConfig,
+}
+impl Migration for NameColumnsMigration {
+    type ConfigType = NameColumnsConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let NameColumnsConfig { path, .. } = &self.config;
+        println!("Naming blank headers in path {}", &path.blue());
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_files(&files, &self.config.common, |file| self.name_columns(file))
+    }
+}
+
+impl NameColumnsMigration {
+    fn name_columns(&self, path: &PathBuf) -> Result<bool, Box<dyn Error>> {
+        let NameColumnsConfig {
+            names,
+            prefix,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        let headers = reader.headers()?.clone();
+        let mut new_headers: Vec<String> = headers.iter().map(String::from).collect();
+        let mut names = names.iter();
+        let mut assigned = HashSet::new();
+        let mut named = 0;
+        for (i, header) in new_headers.iter_mut().enumerate() {
+            if !header.trim().is_empty() {
+                continue;
+            }
+            let name = match prefix {
+                Some(prefix) => format!("{}{}", prefix, i + 1),
+                None => match names.next() {
+                    Some(name) => name.clone(),
+                    None => {
+                        warn(format!("No name left for blank header #{}", i + 1));
+                        continue;
+                    }
+                },
+            };
+            if headers.iter().any(|existing| existing == name) || !assigned.insert(name.clone()) {
+                return Err(
+                    format!("Column {} already exists, can't name #{}", name, i + 1).into(),
+                );
+            }
+            println!("#{} -> {}", i + 1, name);
+            *header = name;
+            named += 1;
+        }
+        if named == 0 && !common.drops_preamble(preamble) {
+            return Ok(false);
+        }
+        writer.write_record(&new_headers)?;
+
+        let output = self.copy_data_rows(data, &reader, writer)?;
+        self.write_if_changed(path, &content, output)
+    }
+}
+
+/// One line of a `--record-permutation` file
+#[derive(Serialize, Deserialize)]
+struct PermutationEntry {
+    path: String,
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+struct UnreorderMigration {
+    config: UnreorderConfig,
+}
+impl Migration for UnreorderMigration {
+    type ConfigType = UnreorderConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let UnreorderConfig {
+            path, permutations, ..
+        } = &self.config;
+        println!(
+            "Restoring header order recorded in {} for path {}",
+            &permutations.blue(),
+            &path.blue()
+        );
+
+        let mut recorded: HashMap<PathBuf, Vec<PermutationEntry>> = HashMap::new();
+        let content = fs::read_to_string(permutations).with_path(permutations)?;
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let entry: PermutationEntry = serde_json::from_str(line)?;
+            let recorded_path = fs::canonicalize(&entry.path).unwrap_or(entry.path.clone().into());
+            recorded.entry(recorded_path).or_default().push(entry);
+        }
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        let mut matched = false;
+        self.migrate_files(&files, &self.config.common, |file| {
+            match recorded.get(&fs::canonicalize(file)?) {
+                Some(entries) => {
+                    matched = true;
+                    self.unreorder(file, entries)
+                }
+                None => Ok(FileOutcome::Skipped("no recorded permutation".to_string())),
+            }
+        })?;
+        if !matched {
+            return Err(format!("No file in {} has a recorded permutation", path).into());
+        }
+        Ok(())
+    }
+}
+
+impl UnreorderMigration {
+    fn unreorder(
+        &self,
+        path: &PathBuf,
+        entries: &[PermutationEntry],
+    ) -> Result<FileOutcome, Box<dyn Error>> {
+        let common = &self.config.common;
+        // entries chain from the oldest move to the newest, so the first
+        // "before" is the order to restore
+        for pair in entries.windows(2) {
+            if pair[0].after != pair[1].before {
+                return Err("Recorded permutations don't follow on from each other".into());
+            }
+        }
+        let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+            return Ok(FileOutcome::Unchanged);
+        };
+
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let headers = reader.headers()?.clone();
+        if headers.iter().ne(last.after.iter()) {
+            return Err(format!(
+                "Headers changed since the recorded reorder, expected {}",
+                last.after.join(",")
+            )
+            .into());
+        }
+
+        // map each restored position to its current one, consuming duplicates in order
+        let mut taken = vec![false; headers.len()];
+        let mut order = Vec::with_capacity(first.before.len());
+        for header in &first.before {
+            let Some(index) = (0..headers.len()).find(|&i| !taken[i] && headers[i] == **header)
+            else {
+                return Err(format!("Recorded column {} not found", header).into());
+            };
+            taken[index] = true;
+            order.push(index);
+        }
+        if order.len() != headers.len() {
+            return Err("Recorded orders don't hold the same columns".into());
+        }
+        if order.iter().enumerate().all(|(i, &index)| i == index)
+            && !common.drops_preamble(preamble)
+        {
+            return Ok(FileOutcome::Unchanged);
+        }
+
+        let mut writer = common.writer(path, preamble);
+        writer.write_record(order.iter().map(|&i| &headers[i]))?;
+        for record in reader.records().take(common.sample_size()) {
+            let record = record?;
+            if record.len() < headers.len() {
+                return Err(format!("Value to migrate not found in {:?}", path).into());
+            }
+            writer.write_record(order.iter().map(|&i| &record[i]))?;
+        }
+        Ok(self
+            .write_if_changed(path, &content, writer.into_inner()?)?
+            .into())
+    }
+}
+
+struct SplitBytesMigration {
+    config: SplitBytesConfig,
+}
+impl Migration for SplitBytesMigration {
+    type ConfigType = SplitBytesConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let SplitBytesConfig {
+            path,
+            max_bytes,
+            output_dir,
+            ..
+        } = &self.config;
+        println!(
+            "Splitting {} into parts of at most {} bytes in {}",
+            &path.blue(),
+            &max_bytes.to_string().blue(),
+            &output_dir.blue()
+        );
+
+        let file = Path::new(path);
+        let parts = self.split(file).with_path(file)?;
+        fs::create_dir_all(output_dir).with_path(output_dir)?;
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = file
+            .extension()
+            .unwrap_or(OsStr::new("csv"))
+            .to_string_lossy();
+        // pad to the widest part number so the parts sort in order
+        let width = parts.len().to_string().len();
+        for (i, part) in parts.iter().enumerate() {
+            let name = format!("{}_{:0width$}.{}", stem, i + 1, extension, width = width);
+            let part_path = Path::new(output_dir).join(name);
+            write_atomic(&part_path, part).with_path(&part_path)?;
+        }
+        println!("{} parts written", parts.len());
+        Ok(())
+    }
+}
+
+impl SplitBytesMigration {
+    fn split(&self, path: &Path) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        let SplitBytesConfig {
+            max_bytes, common, ..
+        } = &self.config;
+        let max_bytes = *max_bytes as usize;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+
+        let mut header = common.writer(path, preamble);
+        header.write_record(reader.headers()?)?;
+        let header = header.into_inner()?;
+
+        let mut parts = vec![];
+        let mut part = header.clone();
+        let mut rows = 0;
+        for (i, record) in reader.records().take(common.sample_size()).enumerate() {
+            let mut row = common.writer(path, "");
+            row.write_record(&record?)?;
+            let row = row.into_inner()?;
+            if header.len() + row.len() > max_bytes {
+                return Err(format!(
+                    "Row {} takes {} bytes with the header, over --max-bytes",
+                    i + 1,
+                    header.len() + row.len()
+                )
+                .into());
+            }
+            if part.len() + row.len() > max_bytes {
+                parts.push(std::mem::replace(&mut part, header.clone()));
+                rows = 0;
+            }
+            part.extend_from_slice(&row);
+            rows += 1;
+        }
+        // a file with no data rows still yields one part with its header
+        if rows > 0 || parts.is_empty() {
+            parts.push(part);
+        }
+        Ok(parts)
+    }
+}
+
+struct TrimColumnsMigration {
+    config: TrimColumnsConfig,
+}
+impl Migration for TrimColumnsMigration {
+    type ConfigType = TrimColumnsConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let TrimColumnsConfig { path, .. } = &self.config;
+        println!("Dropping empty trailing columns in path {}", &path.blue());
+
+        let files = self.get_csv_files(path, &self.config.common)?;
+        self.migrate_files(&files, &self.config.common, |file| self.trim_columns(file))
+    }
+}
+
+impl TrimColumnsMigration {
+    fn trim_columns(&self, path: &PathBuf) -> Result<bool, Box<dyn Error>> {
+        let common = &self.config.common;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        let headers = reader.headers()?.clone();
+        let rows = reader
+            .records()
+            .take(common.sample_size())
+            .collect::<Result<Vec<_>, _>>()?;
+        let truncated = reader.records().next().is_some();
+        let empty = |i: usize| {
+            headers[i].is_empty()
+                && rows
+                    .iter()
+                    .all(|row| row.get(i).unwrap_or_default().is_empty())
+        };
+        // the first column always stays so every line keeps a field
+        let kept = (1..headers.len())
+            .rev()
+            .find(|&i| !empty(i))
+            .map_or(1, |i| i + 1);
+        let dropped = headers.len() - kept;
+        if dropped == 0 && !truncated && !common.drops_preamble(preamble) {
+            return Ok(false);
+        }
+        println!("Dropping {} trailing columns", dropped);
+
+        writer.write_record(headers.iter().take(kept))?;
+        for row in &rows {
+            writer.write_record(row.iter().take(kept))?;
+        }
+        self.write_if_changed(path, &content, writer.into_inner()?)
+    }
+}
+
+struct FillFromMigration {
+    config: FillFromConfig,
+}
+impl Migration for FillFromMigration {
+    type ConfigType = FillFromConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let FillFromConfig {
+            path,
+            column,
+            values_file,
+            common,
+            ..
+        } = &self.config;
+        println!(
+            "Filling {} from {} in path {}",
+            &column.blue(),
+            &values_file.blue(),
+            &path.blue()
+        );
+
+        let content = fs::read_to_string(values_file).with_path(values_file)?;
+        let values: Vec<&str> = content.lines().collect();
+        let files = self.get_csv_files(path, common)?;
+        // files done in an earlier run consumed a value per filled row
+        let done = common.checkpointed()?;
+        let mut next = 0;
+        for file in files.iter().filter(|file| done.contains(*file)) {
+            next += self.filled_rows(file).with_path(file)?;
+        }
+        // only advanced once a file succeeds, so a retried file reuses its values
+        self.migrate_files(&files, common, |file| {
+            let (changed, rows) = self.fill(file, &values[next.min(values.len())..])?;
+            next += rows;
+            Ok(changed)
+        })?;
+        if next != values.len() {
+            warn(format!(
+                "{} values for {} rows in {}",
+                values.len(),
+                next,
+                values_file
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl FillFromMigration {
+    fn filled_rows(&self, path: &Path) -> Result<usize, Box<dyn Error>> {
+        let common = &self.config.common;
+        let content = common.read(path)?;
+        let (_, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut rows = 0;
+        for record in reader.records().take(common.sample_size()) {
+            record?;
+            rows += 1;
+        }
+        Ok(rows)
+    }
+
+    fn fill(&self, path: &PathBuf, values: &[&str]) -> Result<(bool, usize), Box<dyn Error>> {
+        let FillFromConfig {
+            column,
+            order,
+            common,
+            ..
+        } = &self.config;
+        let content = common.read(path)?;
+        let (preamble, data) = common.split_preamble(&content);
+        let mut reader = common.reader(path, data);
+        let mut writer = common.writer(path, preamble);
+
+        let headers = reader.headers()?.clone();
+        let existing = common.matching.find_column(&headers, column)?;
+        let index = match (existing, order) {
+            (Some(index), _) => index,
+            (None, InsertOrder::Position(order)) => {
+                usize::try_from(order - 1).unwrap_or(usize::MAX)
+            }
+            (None, InsertOrder::Last) => headers.len(),
+        };
+        if index > headers.len() {
+            return Err(format!(
+                "Order {} out of range for {:?} with {} columns",
+                order,
+                path,
+                headers.len()
+            )
+            .into());
+        }
+        let mut new_headers: Vec<&str> = headers.iter().collect();
+        if existing.is_none() {
+            new_headers.insert(index, column);
+        }
+        writer.write_record(&new_headers)?;
+
+        let mut rows = 0;
+        for record in reader.records().take(common.sample_size()) {
+            let record = record?;
+            let value = match values.get(rows) {
+                Some(value) => value,
+                None if common.strict => {
+                    return Err(format!("Values ran out at row {} of {:?}", rows + 1, path).into())
+                }
+                None => "",
+            };
+            let mut new_record: Vec<&str> = record.iter().collect();
+            match existing {
+                Some(_) if index >= new_record.len() => {
+                    return Err(format!("Value to migrate not found in {:?}", path).into());
+                }
+                Some(_) => new_record[index] = value,
+                None => new_record.insert(index.min(new_record.len()), value),
+            }
+            writer.write_record(&new_record)?;
+            rows += 1;
+        }
+
+        let changed = self.write_if_changed(path, &content, writer.into_inner()?)?;
+        Ok((changed, rows))
+    }
+}
+
+#[derive(Default)]
+struct HeaderUsage {
+    files: u64,
+    min_position: usize,
+    max_position: usize,
+}
+
+struct HeaderInventoryMigration {
+    config: HeaderInventoryConfig,
+}
+impl Migration for HeaderInventoryMigration {
+    type ConfigType = HeaderInventoryConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let HeaderInventoryConfig {
+            path,
+            output,
+            common,
+        } = &self.config;
+        println!(
+            "Writing header inventory of path {} to {}",
+            &path.blue(),
+            &output.blue()
+        );
+
+        // headers in the order they are first seen
+        let mut inventory: IndexMap<String, HeaderUsage> = IndexMap::new();
+        let mut files = 0;
+        for file in self.iter_csv_files(path, common) {
+            let file = file?;
+            let content = common.read(&file).with_path(&file)?;
+            let (_, data) = common.split_preamble(&content);
+            let headers = common
+                .reader(&file, data)
+                .headers()
+                .with_path(&file)?
+                .clone();
+            let mut seen = HashSet::new();
+            for (i, header) in headers.iter().enumerate() {
+                let usage = inventory.entry(header.to_string()).or_insert(HeaderUsage {
+                    min_position: i + 1,
+                    ..HeaderUsage::default()
+                });
+                if seen.insert(header) {
+                    usage.files += 1;
+                }
+                usage.min_position = usage.min_position.min(i + 1);
+                usage.max_position = usage.max_position.max(i + 1);
+            }
+            files += 1;
+        }
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(["header", "files", "min_position", "max_position"])?;
+        for (header, usage) in &inventory {
+            writer.write_record([
+                header,
+                &usage.files.to_string(),
+                &usage.min_position.to_string(),
+                &usage.max_position.to_string(),
+            ])?;
+        }
+        write_atomic(output, writer.into_inner()?).with_path(output)?;
+        println!("{} headers in {} files", inventory.len(), files);
+        Ok(())
+    }
+}
+
+struct JoinMigration {
+    config: JoinConfig,
+}
+impl Migration for JoinMigration {
+    type ConfigType = JoinConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let JoinConfig {
+            left,
+            right,
+            on,
+            output,
+            how,
+            matching,
+        } = &self.config;
+        println!(
+            "Joining {} with {} on {} into {}",
+            &left.blue(),
+            &right.blue(),
+            &on.blue(),
+            &output.blue()
+        );
+        check_output_not_input(output, &[left, right])?;
+
+        let mut right_reader = csv::Reader::from_path(right).with_path(right)?;
+        let right_headers = right_reader.headers()?.clone();
+        let right_key_index = matching
+            .find_column(&right_headers, on)?
+            .ok_or(format!("Column {} not found in {}", on, right))?;
+        // duplicate keys keep every row, in file order
+        let mut right_rows: HashMap<String, Vec<StringRecord>> = HashMap::new();
+        for record in right_reader.records() {
+            let record = record?;
+            let key = record.get(right_key_index).unwrap_or_default().to_string();
+            right_rows.entry(key).or_default().push(record);
+        }
+
+        let mut left_reader = csv::Reader::from_path(left).with_path(left)?;
+        let left_headers = left_reader.headers()?.clone();
+        let left_key_index = matching
+            .find_column(&left_headers, on)?
+            .ok_or(format!("Column {} not found in {}", on, left))?;
+        let mut writer = csv::Writer::from_writer(vec![]);
+
+        // headers
+        let mut new_headers = left_headers.clone();
+        for (i, header) in right_headers.iter().enumerate() {
+            if i == right_key_index {
+                continue;
+            }
+            let header = if left_headers.iter().any(|h| h == header) {
+                format!("right_{}", header)
+            } else {
+                header.to_string()
+            };
+            if new_headers.iter().any(|h| h == header) {
+                return Err(format!("Column {} would appear twice in {}", header, output).into());
+            }
+            new_headers.push_field(&header);
+        }
+        writer.write_record(&new_headers)?;
+
+        // values
+        let empty_right = vec![""; right_headers.len()];
+        for left_record in left_reader.records() {
+            let left_record = left_record?;
+            let key = left_record.get(left_key_index).unwrap_or_default();
+            let matches: Vec<Vec<&str>> = match right_rows.get(key) {
+                Some(records) => records.iter().map(|r| r.iter().collect()).collect(),
+                None if *how == JoinHow::Left => vec![empty_right.clone()],
+                None => continue,
+            };
+            for right_record in matches {
+                let mut new_record = left_record.clone();
+                for (i, value) in right_record.iter().enumerate() {
+                    if i != right_key_index {
+                        new_record.push_field(value);
+                    }
+                }
+                writer.write_record(&new_record)?;
+            }
+        }
+
+        write_atomic(output, writer.into_inner()?).with_path(output)?;
+        Ok(())
+    }
+}
+
+struct DiffMigration {
+    config: DiffConfig,
+}
+impl Migration for DiffMigration {
+    type ConfigType = DiffConfig;
+
+    fn new(config: Self::ConfigType) -> Self {
+        Self { config }
+    }
+
+    fn run(&self) -> Result<(), Box<dyn Error>> {
+        let DiffConfig {
+            a,
+            b,
+            key,
+            matching,
+        } = &self.config;
+        println!("Diffing {} against {}", &a.blue(), &b.blue());
+
+        let mut a_reader = csv::Reader::from_path(a).with_path(a)?;
+        let mut b_reader = csv::Reader::from_path(b).with_path(b)?;
+        let a_headers = a_reader.headers()?.clone();
+        let b_headers = b_reader.headers()?.clone();
+        let a_records = a_reader.records().collect::<Result<Vec<_>, _>>()?;
+        let b_records = b_reader.records().collect::<Result<Vec<_>, _>>()?;
+        let mut differences = 0;
+
+        // headers
+        for header in a_headers
+            .iter()
+            .filter(|h| !b_headers.iter().any(|b| b == *h))
+        {
+            println!("{}", format!("- column {}", header).red());
+            differences += 1;
+        }
+        for header in b_headers
+            .iter()
+            .filter(|h| !a_headers.iter().any(|a| a == *h))
+        {
+            println!("{}", format!("+ column {}", header).green());
+            differences += 1;
+        }
+        // rows are compared by header name, on the columns both files share
+        let shared: Vec<(usize, usize)> = a_headers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, header)| Some((i, b_headers.iter().position(|b| b == header)?)))
+            .collect();
+        let in_b_order = |(_, j): &(usize, usize)| *j;
+        if !shared.is_sorted_by_key(in_b_order) {
+            println!(
+                "{}",
+                format!(
+                    "~ column order: {} -> {}",
+                    display_record(&a_headers),
+                    display_record(&b_headers)
+                )
+                .yellow()
+            );
+            differences += 1;
+        }
+        let mut compare =
+            |label: &str, a_record: Option<&StringRecord>, b_record: Option<&StringRecord>| {
+                match (a_record, b_record) {
+                    (Some(a_record), None) => {
+                        println!("{}", format!("- {}", display_record(a_record)).red());
+                    }
+                    (None, Some(b_record)) => {
+                        println!("{}", format!("+ {}", display_record(b_record)).green());
+                    }
+                    (Some(a_record), Some(b_record))
+                        if shared
+                            .iter()
+                            .any(|&(i, j)| a_record.get(i) != b_record.get(j)) =>
+                    {
+                        print_changed(label, a_record, b_record);
+                    }
+                    _ => return,
+                }
+                differences += 1;
+            };
+
+        // values
+        match key {
+            Some(key) => {
+                let key_index =
+                    |headers: &StringRecord, file: &str| -> Result<usize, Box<dyn Error>> {
+                        Ok(matching
+                            .find_column(headers, key)?
+                            .ok_or(format!("Column {} not found in {}", key, file))?)
+                    };
+                let a_by_key = group_by_key(&a_records, key_index(&a_headers, a)?);
+                let b_by_key = group_by_key(&b_records, key_index(&b_headers, b)?);
+                for (file, by_key) in [(a, &a_by_key), (b, &b_by_key)] {
+                    for (key, records) in by_key.iter().filter(|(_, records)| records.len() > 1) {
+                        warn(format!(
+                            "Key {} repeats {} times in {}",
+                            key,
+                            records.len(),
+                            file
+                        ));
+                    }
+                }
+                // rows sharing a key are paired up in file order
+                for (key, a_group) in &a_by_key {
+                    let b_group = b_by_key.get(key).map(Vec::as_slice).unwrap_or_default();
+                    for i in 0..a_group.len().max(b_group.len()) {
+                        compare(key, a_group.get(i).copied(), b_group.get(i).copied());
+                    }
+                }
+                for (key, b_group) in &b_by_key {
+                    if !a_by_key.contains_key(key) {
+                        for &b_record in b_group {
+                            compare(key, None, Some(b_record));
+                        }
+                    }
+                }
+            }
+            None => {
+                for i in 0..a_records.len().max(b_records.len()) {
+                    compare(
+                        &format!("row {}", i + 1),
+                        a_records.get(i),
+                        b_records.get(i),
+                    );
+                }
+            }
+        }
+
+        if differences > 0 {
+            return Err(format!("{} differences found", differences).into());
+        }
+        Ok(())
+    }
+}
+
+fn group_by_key(records: &[StringRecord], index: usize) -> IndexMap<&str, Vec<&StringRecord>> {
+    let mut groups: IndexMap<&str, Vec<&StringRecord>> = IndexMap::new();
+    for record in records {
+        let key = record.get(index).unwrap_or_default();
+        groups.entry(key).or_default().push(record);
+    }
+    groups
+}
+
+fn display_record(record: &StringRecord) -> String {
+    record.iter().collect::<Vec<_>>().join(",")
+}
+
+fn print_changed(label: &str, a_record: &StringRecord, b_record: &StringRecord) {
+    println!(
+        "{}",
+        format!(
+            "~ {}: {} -> {}",
+            label,
+            display_record(a_record),
+            display_record(b_record)
+        )
+        .yellow()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::{Read, Write},
+    };
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    fn temp_dir() -> TempDir {
+        tempfile::Builder::new()
+            .prefix("csv-migrator-")
+            .tempdir()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_insert_column() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let mut path = PathBuf::new();
+        path.push(format!("{}/test.csv", test_dir));
+        let mut file = File::create(path.clone()).unwrap();
+        file.write_all(
+            b"H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB1,B2,B3,B4,B5,B6,B7,B8,B9",
+        )
+        .unwrap();
+
+        let cli = Cli {
+            command: Commands::Insert(InsertConfig {
+                path: test_dir.to_string(),
+                column: "H_new".to_string(),
+                default_value: Some("V_new".to_string()),
+                template: None,
+                default_env: None,
+                default_builtin: None,
+                order: InsertOrder::Position(3),
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        let mut modified_file = File::open(path.clone()).unwrap();
+        let mut modified_content = String::new();
+        modified_file.read_to_string(&mut modified_content).unwrap();
+        assert_eq!(
+            modified_content,
+            String::from(
+                "H1,H2,H_new,H3,H4,H5,H6,H7,H8,H9\nA1,A2,V_new,A3,A4,A5,A6,A7,A8,A9\nB1,B2,V_new,B3,B4,B5,B6,B7,B8,B9\n"
+            )
+        )
+    }
+
+    #[test]
+    fn test_insert_column_boundaries() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "H1,H2,H3\nA1,A2,A3\nB1,B2,B3";
+
+        let insert_boundary_test_cases = [
+            (1, "H_new,H1,H2,H3\nV_new,A1,A2,A3\nV_new,B1,B2,B3\n"),
+            (3, "H1,H2,H_new,H3\nA1,A2,V_new,A3\nB1,B2,V_new,B3\n"),
+            (4, "H1,H2,H3,H_new\nA1,A2,A3,V_new\nB1,B2,B3,V_new\n"),
+        ];
+        for (order, expected) in insert_boundary_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Insert(InsertConfig {
+                    path: test_dir.to_string(),
+                    column: "H_new".to_string(),
+                    default_value: Some("V_new".to_string()),
+                    template: None,
+                    default_env: None,
+                    default_builtin: None,
+                    order: InsertOrder::Position(order),
+                    only_header: false,
+                    verify_after: true,
+                    diff_columns: false,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(
+                fs::read_to_string(&path).unwrap(),
+                expected,
+                "order {}",
+                order
+            );
+        }
+    }
+
+    #[test]
+    fn test_reorder_column() {
+        let reorder_test_cases = [
+            ("H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB1,B2,B3,B4,B5,B6,B7,B8,B9".to_string(), "H3,H1,H2,H4,H5,H6,H7,H8,H9\nA3,A1,A2,A4,A5,A6,A7,A8,A9\nB3,B1,B2,B4,B5,B6,B7,B8,B9\n".to_string(), "H3", 1),
+            ("H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB,B,B,B4,B5,B6,B7,B8,B9".to_string(), "H3,H1,H2,H4,H5,H6,H7,H8,H9\nA3,A1,A2,A4,A5,A6,A7,A8,A9\nB,B,B,B4,B5,B6,B7,B8,B9\n".to_string(), "H3", 1),
+            ("H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB1,B2,B3,B4,B5,B6,B7,B8,B9".to_string(), "H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB1,B2,B3,B4,B5,B6,B7,B8,B9".to_string(), "H1", 1),
+            ("H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB1,B2,B3,B4,B5,B6,B7,B8,B9".to_string(), "H2,H1,H3,H4,H5,H6,H7,H8,H9\nA2,A1,A3,A4,A5,A6,A7,A8,A9\nB2,B1,B3,B4,B5,B6,B7,B8,B9\n".to_string(), "H1", 2),
+
+        ];
+
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        for (i, tc) in reorder_test_cases.iter().enumerate() {
+            let (init, expected, column, order) = tc;
+            let mut path = PathBuf::new();
+            path.push(format!("{}/test_{}.csv", test_dir, i));
+            let mut file = File::create(path.clone()).unwrap();
+            let buff = init.clone().into_bytes();
+            file.write_all(&buff).unwrap();
+
+            let cli = Cli {
+                command: Commands::Reorder(ReorderConfig {
+                    path: test_dir.to_string(),
+                    column: column.to_string(),
+                    occurrence: 1,
+                    order: *order,
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    record_permutation: None,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            let mut modified_file = File::open(path.clone()).unwrap();
+            let mut modified_content = String::new();
+            modified_file.read_to_string(&mut modified_content).unwrap();
+            assert_eq!(modified_content, *expected)
+        }
+    }
+
+    #[test]
+    fn test_join() {
+        let join_test_cases = [
+            (
+                JoinHow::Inner,
+                "id,name,city\n1,Ann,Oslo\n2,Bob,Rome\n3,Cid,Lima\n3,Cid,Kyiv\n",
+            ),
+            (
+                JoinHow::Left,
+                "id,name,city\n1,Ann,Oslo\n2,Bob,Rome\n3,Cid,Lima\n3,Cid,Kyiv\n4,Dan,\n",
+            ),
+        ];
+
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let left = format!("{}/left.csv", test_dir);
+        let right = format!("{}/right.csv", test_dir);
+        fs::write(&left, "id,name\n1,Ann\n2,Bob\n3,Cid\n4,Dan").unwrap();
+        fs::write(&right, "city,id\nOslo,1\nRome,2\nLima,3\nKyiv,3\nPisa,5").unwrap();
+        for (i, (how, expected)) in join_test_cases.iter().enumerate() {
+            let output = format!("{}/output_{}.csv", test_dir, i);
+            let cli = Cli {
+                command: Commands::Join(JoinConfig {
+                    left: left.clone(),
+                    right: right.clone(),
+                    on: "id".to_string(),
+                    output: output.clone(),
+                    how: *how,
+                    matching: MatchConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&output).unwrap(), *expected)
+        }
+
+        let cli = Cli {
+            command: Commands::Join(JoinConfig {
+                left: left.clone(),
+                right: right.clone(),
+                on: "id".to_string(),
+                output: left.clone(),
+                how: JoinHow::Inner,
+                matching: MatchConfig::default(),
+            }),
+        };
+        assert!(run(cli).is_err());
+        assert_eq!(
+            fs::read_to_string(&left).unwrap(),
+            "id,name\n1,Ann\n2,Bob\n3,Cid\n4,Dan"
+        );
+
+        // city from the right would be renamed to a right_city left already has
+        let clashing = format!("{}/clashing.csv", test_dir);
+        fs::write(&clashing, "id,city,right_city\n1,Oslo,Bergen\n").unwrap();
+        let output = format!("{}/output_clash.csv", test_dir);
+        let cli = Cli {
+            command: Commands::Join(JoinConfig {
+                left: clashing,
+                right: right.clone(),
+                on: "id".to_string(),
+                output: output.clone(),
+                how: JoinHow::Inner,
+                matching: MatchConfig::default(),
+            }),
+        };
+        assert!(run(cli).is_err());
+        assert!(!Path::new(&output).exists());
+    }
+
+    #[test]
+    fn test_diff() {
+        let diff_test_cases = [
+            ("H1,H2\nA1,A2\nB1,B2", None, true),
+            ("H1,H2\nA1,A2\nB1,B_changed", None, false),
+            ("H1,H2\nA1,A2", None, false),
+            ("H1,H3\nA1,A2\nB1,B2", None, false),
+            ("H1,H2\nB1,B2\nA1,A2", None, false),
+            ("H1,H2\nB1,B2\nA1,A2", Some("H1"), true),
+            ("H1,H2\nB1,B2\nC1,C2", Some("H1"), false),
+            ("H1,H2\nA1,A2\nB1,B2\nB1,B2", Some("H1"), false),
+            ("H2,H1\nA2,A1\nB2,B1", None, false),
+        ];
+
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let a = format!("{}/a.csv", test_dir);
+        fs::write(&a, "H1,H2\nA1,A2\nB1,B2").unwrap();
+        for (i, (b_content, key, identical)) in diff_test_cases.iter().enumerate() {
+            let b = format!("{}/b_{}.csv", test_dir, i);
+            fs::write(&b, b_content).unwrap();
+            let cli = Cli {
+                command: Commands::Diff(DiffConfig {
+                    a: a.clone(),
+                    b,
+                    key: key.map(String::from),
+                    matching: MatchConfig::default(),
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), *identical)
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        fs::write(&path, "H1,H2,H3\nA1,A2,A3\nB1,B2,B3\nC1,C2,C3").unwrap();
+
+        let cli = Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H3".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig {
+                    sample: Some(2),
+                    ..Default::default()
+                },
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "H3,H1,H2\nA3,A1,A2\nB3,B1,B2\n"
+        )
+    }
+
+    #[test]
+    fn test_reorder_noop_leaves_file_untouched() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "H1,\"H2\",H3\r\nA1, A2 ,\"A3\"";
+        fs::write(&path, content).unwrap();
+
+        let cli = Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H2".to_string(),
+                occurrence: 1,
+                order: 2,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content)
+    }
+
+    #[test]
+    fn test_unchanged_file_not_rewritten() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = PathBuf::from(format!("{}/test.csv", test_dir));
+        let content = "H1,H2\nA1,A2\n";
+        fs::write(&path, content).unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+        let migration = ReorderMigration::new(ReorderConfig {
+            path: test_dir.to_string(),
+            column: "H1".to_string(),
+            occurrence: 1,
+            order: 1,
+            only_header: false,
+            verify_after: false,
+            diff_columns: false,
+            record_permutation: None,
+            common: CommonConfig::default(),
+        });
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(["H1", "H2"]).unwrap();
+        writer.write_record(["A1", "A2"]).unwrap();
+        let output = writer.into_inner().unwrap();
+        assert!(!migration.write_if_changed(&path, content, output).unwrap());
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(["H2", "H1"]).unwrap();
+        let output = writer.into_inner().unwrap();
+        assert!(migration.write_if_changed(&path, content, output).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "H2,H1\n")
+    }
+
+    #[test]
+    fn test_only_header() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let data = "A1,\"A 2\",A3\r\nB1,B2,B3";
+
+        fs::write(&path, format!("H1,H2,H3\r\n{}", data)).unwrap();
+        let cli = Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H3".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: true,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("H3,H1,H2\r\n{}", data)
+        );
+
+        fs::write(&path, format!("H1,H2,H3\r\n{}", data)).unwrap();
+        let cli = Cli {
+            command: Commands::Insert(InsertConfig {
+                path: test_dir.to_string(),
+                column: "H_new".to_string(),
+                default_value: Some("V_new".to_string()),
+                template: None,
+                default_env: None,
+                default_builtin: None,
+                order: InsertOrder::Position(2),
+                only_header: true,
+                verify_after: false,
+                diff_columns: false,
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("H1,H_new,H2,H3\r\n{}", data)
+        )
+    }
+
+    #[test]
+    fn test_insert_template() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "first,last\nAnn,Lee\nBob,Ray";
+        fs::write(&path, content).unwrap();
+
+        let insert = |template: &str| Cli {
+            command: Commands::Insert(InsertConfig {
+                path: test_dir.to_string(),
+                column: "full".to_string(),
+                default_value: None,
+                template: Some(template.to_string()),
+                default_env: None,
+                default_builtin: None,
+                order: InsertOrder::Position(2),
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                common: CommonConfig::default(),
+            }),
+        };
+        assert!(run(insert("{first}-{middle}")).is_err());
+        assert!(run(insert("{first")).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+        run(insert("<{last}, {first}>")).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "first,full,last\nAnn,\"<Lee, Ann>\",Lee\nBob,\"<Ray, Bob>\",Ray\n"
+        )
+    }
+
+    #[test]
+    fn test_insert_default_sources() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let insert = |default_env: Option<&str>, default_builtin: Option<Builtin>| Cli {
+            command: Commands::Insert(InsertConfig {
+                path: test_dir.to_string(),
+                column: "H_new".to_string(),
+                default_value: None,
+                template: None,
+                default_env: default_env.map(String::from),
+                default_builtin,
+                order: InsertOrder::Position(1),
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                common: CommonConfig::default(),
+            }),
+        };
+        let values = || {
+            let mut reader = csv::Reader::from_path(&path).unwrap();
+            reader
+                .records()
+                .map(|r| r.unwrap()[0].to_string())
+                .collect::<Vec<_>>()
+        };
+
+        fs::write(&path, "H1\nA1\nB1").unwrap();
+        env::set_var("CSV_MIGRATOR_TEST_DEFAULT", "from_env");
+        run(insert(Some("CSV_MIGRATOR_TEST_DEFAULT"), None)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "H_new,H1\nfrom_env,A1\nfrom_env,B1\n"
+        );
+        assert!(run(insert(Some("CSV_MIGRATOR_TEST_UNSET"), None)).is_err());
+
+        fs::write(&path, "H1\nA1\nB1").unwrap();
+        run(insert(None, Some(Builtin::Now))).unwrap();
+        let now = values();
+        assert_eq!(now[0], now[1]);
+        assert!(chrono::DateTime::parse_from_rfc3339(&now[0]).is_ok());
+
+        fs::write(&path, "H1\nA1\nB1").unwrap();
+        run(insert(None, Some(Builtin::Uuid))).unwrap();
+        let uuids = values();
+        assert_ne!(uuids[0], uuids[1]);
+        assert!(uuids.iter().all(|v| Uuid::parse_str(v).is_ok()))
+    }
+
+    #[test]
+    fn test_insert_last() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let narrow = format!("{}/narrow.csv", test_dir);
+        let wide = format!("{}/wide.csv", test_dir);
+        fs::write(&narrow, "H1,H2\nA1,A2").unwrap();
+        fs::write(&wide, "H1,H2,H3\nA1,A2,A3").unwrap();
+
+        let insert = |order: InsertOrder| Cli {
+            command: Commands::Insert(InsertConfig {
+                path: test_dir.to_string(),
+                column: "H_new".to_string(),
+                default_value: Some("V_new".to_string()),
+                template: None,
+                default_env: None,
+                default_builtin: None,
+                order,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                common: CommonConfig::default(),
+            }),
+        };
+        run(insert(InsertOrder::Last)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&narrow).unwrap(),
+            "H1,H2,H_new\nA1,A2,V_new\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&wide).unwrap(),
+            "H1,H2,H3,H_new\nA1,A2,A3,V_new\n"
+        );
+        assert!(run(insert(InsertOrder::Position(6))).is_err());
+        assert!(run(insert(InsertOrder::Position(0))).is_err());
+        assert_eq!("last".parse(), Ok(InsertOrder::Last));
+        assert_eq!("2".parse(), Ok(InsertOrder::Position(2)));
+        assert!("first".parse::<InsertOrder>().is_err())
+    }
+
+    #[test]
+    fn test_reorder_order_range() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "H1,H2,H3\nA1,A2,A3";
+        fs::write(&path, content).unwrap();
+
+        let reorder = |order: i32| Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H1".to_string(),
+                occurrence: 1,
+                order,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig::default(),
+            }),
+        };
+        let err = run(reorder(4)).unwrap_err().to_string();
+        assert!(err.contains("test.csv") && err.contains("3 columns"));
+        assert!(run(reorder(0)).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+        run(reorder(3)).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "H2,H3,H1\nA2,A3,A1\n")
+    }
+
+    #[test]
+    fn test_flatten() {
+        let flatten_test_cases = [
+            (None, "H1,H2\nA1 a,A2  a\nB1,B2\n"),
+            (Some("H2"), "H1,H2\n\"A1\na\",A2  a\nB1,B2\n"),
+        ];
+
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        for (column, expected) in flatten_test_cases {
+            fs::write(&path, "H1,H2\n\"A1\na\",\"A2\r\n\ra\"\nB1,B2").unwrap();
+            let cli = Cli {
+                command: Commands::Flatten(FlattenConfig {
+                    path: test_dir.to_string(),
+                    column: column.map(String::from),
+                    replacement: " ".to_string(),
+                    dry_run: false,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected)
+        }
+    }
+
+    #[test]
+    fn test_header_row() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "Report,\"Q1\nexport\"\ngenerated today\nH1,H2\nA1,A2";
+
+        for keep_preamble in [true, false] {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Reorder(ReorderConfig {
+                    path: test_dir.to_string(),
+                    column: "H2".to_string(),
+                    occurrence: 1,
+                    order: 1,
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    record_permutation: None,
+                    common: CommonConfig {
+                        header_row: 4,
+                        keep_preamble,
+                        ..Default::default()
+                    },
+                }),
+            };
+            run(cli).unwrap();
+            let expected = if keep_preamble {
+                "Report,\"Q1\nexport\"\ngenerated today\nH2,H1\nA2,A1\n"
+            } else {
+                "H2,H1\nA2,A1\n"
+            };
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected)
+        }
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let ignore_case_test_cases = [
+            ("Email,Name\nA1,A2", Some("Name,Email\nA2,A1\n")),
+            ("EMAIL,Name\nA1,A2", Some("Name,EMAIL\nA2,A1\n")),
+            ("Email,email\nA1,A2", None),
+        ];
+
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        for (content, expected) in ignore_case_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Reorder(ReorderConfig {
+                    path: test_dir.to_string(),
+                    column: "email".to_string(),
+                    occurrence: 1,
+                    order: 2,
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    record_permutation: None,
+                    common: CommonConfig {
+                        matching: MatchConfig {
+                            ignore_case: true,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                }),
+            };
+            match expected {
+                Some(expected) => {
+                    run(cli).unwrap();
+                    assert_eq!(fs::read_to_string(&path).unwrap(), expected)
+                }
+                None => assert!(run(cli).is_err()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_trim_match() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        fs::write(&path, "name, email \nA1,A2").unwrap();
+
+        let cli = Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "email".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig {
+                    matching: MatchConfig {
+                        trim_match: true,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), " email ,name\nA2,A1\n")
+    }
+
+    #[test]
+    fn test_reorder_adjacent() {
+        let reorder_adjacent_test_cases = [
+            (2, "H1,H3,H2,H4,H5\nA1,A3,A2,A4,A5\n"),
+            (3, "H1,H2,H3,H4,H5\nA1,A2,A3,A4,A5"),
+            (4, "H1,H2,H4,H3,H5\nA1,A2,A4,A3,A5\n"),
+        ];
+
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        for (order, expected) in reorder_adjacent_test_cases {
+            fs::write(&path, "H1,H2,H3,H4,H5\nA1,A2,A3,A4,A5").unwrap();
+            let cli = Cli {
+                command: Commands::Reorder(ReorderConfig {
+                    path: test_dir.to_string(),
+                    column: "H3".to_string(),
+                    occurrence: 1,
+                    order,
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    record_permutation: None,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected)
+        }
+    }
+
+    #[test]
+    fn test_extensions_and_delimiter() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let csv = format!("{}/test.csv", test_dir);
+        let tsv = format!("{}/test.TSV", test_dir);
+        let txt = format!("{}/test.txt", test_dir);
+
+        let reorder = |extensions: &[&str], delimiter: Option<u8>| Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H2".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig {
+                    extensions: extensions.iter().map(|e| e.to_string()).collect(),
+                    delimiter,
+                    ..Default::default()
+                },
+            }),
+        };
+        fs::write(&csv, "H1,H2\nA1,A2").unwrap();
+        fs::write(&tsv, "H1\tH2\nA1\tA,2").unwrap();
+        fs::write(&txt, "H1;H2\nA1;A2").unwrap();
+        run(reorder(&["tsv"], None)).unwrap();
+        assert_eq!(fs::read_to_string(&csv).unwrap(), "H1,H2\nA1,A2");
+        assert_eq!(fs::read_to_string(&tsv).unwrap(), "H2\tH1\nA,2\tA1\n");
+
+        run(reorder(&[".txt"], Some(b';'))).unwrap();
+        assert_eq!(fs::read_to_string(&txt).unwrap(), "H2;H1\nA2;A1\n");
+        assert_eq!(parse_delimiter("\\t"), Ok(b'\t'));
+        assert!(parse_delimiter(";;").is_err())
+    }
+
+    #[test]
+    fn test_hidden() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        fs::create_dir_all(format!("{}/.git", test_dir)).unwrap();
+        let visible = format!("{}/test.csv", test_dir);
+        let hidden_file = format!("{}/.test.csv", test_dir);
+        let hidden_dir_file = format!("{}/.git/test.csv", test_dir);
+        for hidden in [false, true] {
+            for path in [&visible, &hidden_file, &hidden_dir_file] {
+                fs::write(path, "H1,H2\nA1,A2").unwrap();
+            }
+            let cli = Cli {
+                command: Commands::Reorder(ReorderConfig {
+                    path: test_dir.to_string(),
+                    column: "H2".to_string(),
+                    occurrence: 1,
+                    order: 1,
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    record_permutation: None,
+                    common: CommonConfig {
+                        hidden,
+                        ..Default::default()
+                    },
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&visible).unwrap(), "H2,H1\nA2,A1\n");
+            for path in [&hidden_file, &hidden_dir_file] {
+                let expected = if hidden {
+                    "H2,H1\nA2,A1\n"
+                } else {
+                    "H1,H2\nA1,A2"
+                };
+                assert_eq!(fs::read_to_string(path).unwrap(), expected);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_loop() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        fs::create_dir_all(format!("{}/data", test_dir)).unwrap();
+        std::os::unix::fs::symlink("..", format!("{}/data/loop", test_dir)).unwrap();
+        let path = format!("{}/data/test.csv", test_dir);
+        fs::write(&path, "H1,H2\nA1,A2").unwrap();
+
+        for follow_symlinks in [false, true] {
+            let migration = ReorderMigration::new(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H2".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig {
+                    follow_symlinks,
+                    ..Default::default()
+                },
+            });
+            let files = migration
+                .get_csv_files(test_dir, &migration.config.common)
+                .unwrap();
+            assert_eq!(files, vec![PathBuf::from(&path)]);
+        }
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        fs::create_dir_all(format!("{}/b/c", test_dir)).unwrap();
+        fs::create_dir_all(format!("{}/dir.csv", test_dir)).unwrap();
+        for file in ["a.csv", "b/b.csv", "b/c/c.csv"] {
+            fs::write(format!("{}/{}", test_dir, file), "H1\nA1").unwrap();
+        }
+
+        let max_depth_test_cases = [
+            (Some(1), vec!["a.csv"]),
+            (Some(2), vec!["a.csv", "b/b.csv"]),
+            (None, vec!["a.csv", "b/b.csv", "b/c/c.csv"]),
+        ];
+        for (max_depth, expected) in max_depth_test_cases {
+            let migration = ReorderMigration::new(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H1".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig {
+                    max_depth,
+                    ..Default::default()
+                },
+            });
+            let files = migration
+                .get_csv_files(test_dir, &migration.config.common)
+                .unwrap();
+            let expected: Vec<PathBuf> = expected
+                .iter()
+                .map(|file| PathBuf::from(test_dir).join(file))
+                .collect();
+            assert_eq!(files, expected);
+        }
+    }
+
+    #[test]
+    fn test_max_file_size() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let small = format!("{}/small.csv", test_dir);
+        let large = format!("{}/large.csv", test_dir);
+
+        for strict in [false, true] {
+            fs::write(&small, "H1,H2\nA1,A2").unwrap();
+            fs::write(&large, "H1,H2\nA1,A2\nB1,B2\nC1,C2").unwrap();
+            let cli = Cli {
+                command: Commands::Reorder(ReorderConfig {
+                    path: test_dir.to_string(),
+                    column: "H2".to_string(),
+                    occurrence: 1,
+                    order: 1,
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    record_permutation: None,
+                    common: CommonConfig {
+                        max_file_size: Some(12),
+                        strict,
+                        ..Default::default()
+                    },
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), !strict);
+            assert_eq!(fs::read_to_string(&small).unwrap(), "H2,H1\nA2,A1\n");
+            assert_eq!(
+                fs::read_to_string(&large).unwrap(),
+                "H1,H2\nA1,A2\nB1,B2\nC1,C2"
+            );
+        }
+    }
+
+    #[test]
+    fn test_io_error_names_file() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let missing = PathBuf::from(format!("{}/missing.csv", test_dir));
+
+        let migration = ReorderMigration::new(ReorderConfig {
+            path: test_dir.to_string(),
+            column: "H1".to_string(),
+            occurrence: 1,
+            order: 1,
+            only_header: false,
+            verify_after: false,
+            diff_columns: false,
+            record_permutation: None,
+            common: CommonConfig::default(),
+        });
+        let err = migration
+            .migrate_files(
+                std::slice::from_ref(&missing),
+                &migration.config.common,
+                |file| migration.shift_column(file, &"H1".to_string(), 1),
+            )
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{}: ", missing.display())));
+        let source = err.source().unwrap().downcast_ref::<std::io::Error>();
+        assert_eq!(source.unwrap().kind(), std::io::ErrorKind::NotFound);
+
+        let cli = Cli {
+            command: Commands::Diff(DiffConfig {
+                a: missing.to_string_lossy().to_string(),
+                b: missing.to_string_lossy().to_string(),
+                key: None,
+                matching: MatchConfig::default(),
+            }),
+        };
+        let err = run(cli).unwrap_err();
+        assert!(err.to_string().contains("missing.csv"))
+    }
+
+    #[test]
+    fn test_empty_and_headers_only() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let empty = format!("{}/empty.csv", test_dir);
+        let headers_only = format!("{}/headers_only.csv", test_dir);
+
+        fs::write(&empty, "").unwrap();
+        fs::write(&headers_only, "H1,H2").unwrap();
+        let cli = Cli {
+            command: Commands::Insert(InsertConfig {
+                path: test_dir.to_string(),
+                column: "H_new".to_string(),
+                default_value: Some("V_new".to_string()),
+                template: None,
+                default_env: None,
+                default_builtin: None,
+                order: InsertOrder::Position(2),
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(fs::read_to_string(&empty).unwrap(), "");
+        assert_eq!(fs::read_to_string(&headers_only).unwrap(), "H1,H_new,H2\n");
+
+        fs::write(&headers_only, "H1,H2").unwrap();
+        let cli = Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H2".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(fs::read_to_string(&empty).unwrap(), "");
+        assert_eq!(fs::read_to_string(&headers_only).unwrap(), "H2,H1\n");
+    }
+
+    #[test]
+    fn test_insert_row() {
+        let insert_row_test_cases = [
+            (1, "H1,H2\nkg,\"m,s\"\nA1,A2\nB1,B2\n"),
+            (3, "H1,H2\nA1,A2\nB1,B2\nkg,\"m,s\"\n"),
+            (4, "H1,H2\nA1,A2\nB1,B2"),
+        ];
+
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let narrow = format!("{}/narrow.csv", test_dir);
+        for (at, expected) in insert_row_test_cases {
+            fs::write(&path, "H1,H2\nA1,A2\nB1,B2").unwrap();
+            fs::write(&narrow, "H1\nA1").unwrap();
+            let cli = Cli {
+                command: Commands::InsertRow(InsertRowConfig {
+                    path: test_dir.to_string(),
+                    values: "kg,\"m,s\"".to_string(),
+                    at,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+            assert_eq!(fs::read_to_string(&narrow).unwrap(), "H1\nA1");
+        }
+    }
+
+    #[test]
+    fn test_head_and_tail() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let output = temp_dir();
+        let output_dir = output.path().to_str().unwrap();
+        fs::create_dir_all(format!("{}/nested", test_dir)).unwrap();
+        let path = format!("{}/nested/test.csv", test_dir);
+        let content = "H1,H2\nA1,A2\nB1,B2\nC1,C2";
+        fs::write(&path, content).unwrap();
+
+        let cli = Cli {
+            command: Commands::Tail(TailConfig {
+                path: test_dir.to_string(),
+                rows: 2,
+                output: OutputConfig {
+                    output_dir: Some(output_dir.to_string()),
+                    ..OutputConfig::default()
+                },
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        assert_eq!(
+            fs::read_to_string(format!("{}/nested/test.csv", output_dir)).unwrap(),
+            "H1,H2\nB1,B2\nC1,C2\n"
+        );
+
+        let cli = Cli {
+            command: Commands::Head(HeadConfig {
+                path: test_dir.to_string(),
+                rows: 1,
+                output: OutputConfig::default(),
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "H1,H2\nA1,A2\n");
+
+        // a malformed row fails the file even when no rows are kept
+        let malformed = "H1,H2\nA1,A2\nB1\n";
+        fs::write(&path, malformed).unwrap();
+        let cli = Cli {
+            command: Commands::Tail(TailConfig {
+                path: test_dir.to_string(),
+                rows: 0,
+                output: OutputConfig::default(),
+                common: CommonConfig::default(),
+            }),
+        };
+        assert!(run(cli).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), malformed);
+    }
+
+    #[test]
+    fn test_manifest() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let output_dir = temp_dir();
+        let output = &format!("{}/manifest.json", output_dir.path().display());
+        fs::create_dir_all(format!("{}/nested", test_dir)).unwrap();
+        fs::write(format!("{}/a.csv", test_dir), "H1\nA1\nB1").unwrap();
+        fs::write(format!("{}/nested/b.csv", test_dir), "").unwrap();
+
+        let cli = Cli {
+            command: Commands::Manifest(ManifestConfig {
+                path: test_dir.to_string(),
+                output: output.to_string(),
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(output).unwrap()).unwrap();
+        assert_eq!(
+            manifest,
+            serde_json::json!([
+                {
+                    "path": "a.csv",
+                    "sha256": "94da3c7da46c31b2d0e6a9ee6bf6d05a4d5a311a7f732e90bb40f5b2d5384f0f",
+                    "rows": 2
+                },
+                {
+                    "path": "nested/b.csv",
+                    "sha256": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                    "rows": 0
+                }
+            ])
+        );
+
+        let cli = Cli {
+            command: Commands::Manifest(ManifestConfig {
+                path: format!("{}/a.csv", test_dir),
+                output: output.to_string(),
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(output).unwrap()).unwrap();
+        assert_eq!(manifest[0]["path"], "a.csv");
+    }
+
+    #[test]
+    fn test_distinct() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        fs::write(format!("{}/a.csv", test_dir), "id,status\n1,done\n2,open").unwrap();
+        fs::write(format!("{}/b.csv", test_dir), "status\nopen\nnew\nopen").unwrap();
+        fs::write(format!("{}/c.csv", test_dir), "id\n3").unwrap();
+
+        let distinct_test_cases = [
+            (
+                DistinctSort::Count,
+                false,
+                [("open", 3), ("done", 1), ("new", 1)],
+            ),
+            (
+                DistinctSort::Value,
+                false,
+                [("done", 1), ("new", 1), ("open", 3)],
+            ),
+            (
+                DistinctSort::Count,
+                true,
+                [("done", 1), ("open", 3), ("new", 1)],
+            ),
+        ];
+        for (sort, preserve_order, expected) in distinct_test_cases {
+            let migration = DistinctMigration::new(DistinctConfig {
+                path: test_dir.to_string(),
+                column: Some("status".to_string()),
+                top: None,
+                dump_distinct: None,
+                sort,
+                preserve_order,
+                common: CommonConfig::default(),
+            });
+            let files = migration.iter_csv_files(test_dir, &migration.config.common);
+            let counts = migration.count_values(files).unwrap();
+            let expected: Vec<(String, u64)> =
+                expected.iter().map(|(v, c)| (v.to_string(), *c)).collect();
+            assert_eq!(migration.sorted(counts), expected);
+        }
+    }
+
+    #[test]
+    fn test_output_path_stays_in_output_dir() {
+        let migration = HeadMigration::new(HeadConfig {
+            path: "data".to_string(),
+            rows: 1,
+            output: OutputConfig::default(),
+            common: CommonConfig::default(),
+        });
+        let output_dir = Some("out".to_string());
+
+        let output_path_test_cases = [
+            ("data", "data/a/b.csv", Some("out/a/b.csv")),
+            ("data/b.csv", "data/b.csv", Some("out/b.csv")),
+            ("data", "data/../../etc/passwd", None),
+            ("data", "data/./../b.csv", None),
+            ("", "/etc/passwd", None),
+        ];
+        for (root, file, expected) in output_path_test_cases {
+            let destination = migration.output_path(root, Path::new(file), &output_dir);
+            assert_eq!(destination.ok(), expected.map(PathBuf::from), "{}", file);
+        }
+    }
+
+    #[test]
+    fn test_cr_records() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        fs::write(&path, "H1,H2\rA1,A2\rB1,B2\r").unwrap();
+
+        let cli = Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: path.clone(),
+                column: "H2".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig {
+                    cr_records: true,
+                    ..CommonConfig::default()
+                },
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "H2,H1\nA2,A1\nB2,B1\n");
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "name,mobile_phone,home_phone\nAnn,111,222\nBob,,333\nCid,,";
+
+        let coalesce = |keep_sources: bool| Cli {
+            command: Commands::Coalesce(CoalesceConfig {
+                path: test_dir.to_string(),
+                into: "phone".to_string(),
+                from: vec!["mobile_phone".to_string(), "home_phone".to_string()],
+                columns_file: None,
+                order: InsertOrder::Position(2),
+                keep_sources,
+                common: CommonConfig::default(),
+            }),
+        };
+        let coalesce_test_cases = [
+            (
+                true,
+                "name,phone,mobile_phone,home_phone\nAnn,111,111,222\nBob,333,,333\nCid,,,\n",
+            ),
+            (false, "name,phone\nAnn,111\nBob,333\nCid,\n"),
+        ];
+        for (keep_sources, expected) in coalesce_test_cases {
+            fs::write(&path, content).unwrap();
+            run(coalesce(keep_sources)).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+
+        let missing = format!("{}/missing.csv", test_dir);
+        fs::write(&missing, "name,home_phone\nAnn,222").unwrap();
+        run(coalesce(true)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&missing).unwrap(),
+            "name,home_phone\nAnn,222"
+        );
+    }
+
+    #[test]
+    fn test_pad() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "city,zip\nA,123\nB,98765\nC,123456\nD,";
+
+        let pad_test_cases = [
+            (
+                '0',
+                PadSide::Left,
+                "city,zip\nA,00123\nB,98765\nC,123456\nD,00000\n",
+            ),
+            (
+                '_',
+                PadSide::Right,
+                "city,zip\nA,123__\nB,98765\nC,123456\nD,_____\n",
+            ),
+        ];
+        for (char, side, expected) in pad_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Pad(PadConfig {
+                    path: test_dir.to_string(),
+                    column: ColumnSelector {
+                        column: Some("zip".to_string()),
+                        column_regex: None,
+                    },
+                    width: 5,
+                    char,
+                    side,
+                    dry_run: false,
+                    row_filter: None,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_slice() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "id,code\n1,ABCD-001\n2,ÄÖÜß-002\n3,AB\n4,";
+
+        let slice_test_cases = [
+            (0, Some(4), "id,code\n1,ABCD\n2,ÄÖÜß\n3,AB\n4,\n"),
+            (5, None, "id,code\n1,001\n2,002\n3,\n4,\n"),
+            (6, Some(2), "id,code\n1,\n2,\n3,\n4,\n"),
+        ];
+        for (start, end, expected) in slice_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Slice(SliceConfig {
+                    path: test_dir.to_string(),
+                    column: ColumnSelector {
+                        column: Some("code".to_string()),
+                        column_regex: None,
+                    },
+                    start,
+                    end,
+                    dry_run: false,
+                    row_filter: None,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_progress_every() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let paths: Vec<String> = (1..=5)
+            .map(|i| format!("{}/test_{}.csv", test_dir, i))
+            .collect();
+        for path in &paths {
+            fs::write(path, "H1,H2\nA1,A2").unwrap();
+        }
+        fs::write(&paths[2], "H2,H1\nA2,A1").unwrap();
+
+        let cli = Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H2".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig {
+                    progress_every: 2,
+                    ..CommonConfig::default()
+                },
+            }),
+        };
+        run(cli).unwrap();
+        for (i, path) in paths.iter().enumerate() {
+            let expected = if i == 2 {
+                "H2,H1\nA2,A1"
+            } else {
+                "H2,H1\nA2,A1\n"
+            };
+            assert_eq!(fs::read_to_string(path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_dry_run_counts_cells() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = PathBuf::from(format!("{}/test.csv", test_dir));
+        let content = "city,zip\nA,123\nB,98765\nC,1";
+        fs::write(&path, content).unwrap();
+
+        let migration = PadMigration::new(PadConfig {
+            path: test_dir.to_string(),
+            column: ColumnSelector {
+                column: Some("zip".to_string()),
+                column_regex: None,
+            },
+            width: 5,
+            char: '0',
+            side: PadSide::Left,
+            dry_run: true,
+            row_filter: None,
+            common: CommonConfig::default(),
+        });
+        let (outcome, cells) = migration.pad(&path).unwrap();
+        assert!(matches!(outcome, FileOutcome::Previewed));
+        assert_eq!(cells, 2);
+        migration.run().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+    }
+
+    #[test]
+    fn test_on_missing_column() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let missing = format!("{}/missing.csv", test_dir);
+
+        let reorder = |on_missing_column: OnMissingColumn, strict: bool| Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H2".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig {
+                    on_missing_column,
+                    strict,
+                    ..CommonConfig::default()
+                },
+            }),
+        };
+        let on_missing_column_test_cases = [
+            (OnMissingColumn::Error, false, false),
+            (OnMissingColumn::WarnSkip, false, true),
+            (OnMissingColumn::WarnSkip, true, false),
+            (OnMissingColumn::SilentSkip, true, true),
+        ];
+        for (on_missing_column, strict, succeeds) in on_missing_column_test_cases {
+            fs::write(&path, "H1,H2\nA1,A2").unwrap();
+            fs::write(&missing, "H1\nA1").unwrap();
+            let result = run(reorder(on_missing_column, strict));
+            assert_eq!(result.is_ok(), succeeds, "{:?}", on_missing_column);
+            if on_missing_column == OnMissingColumn::Error {
+                assert!(result.unwrap_err().to_string().contains("missing.csv"));
+            }
+            assert_eq!(fs::read_to_string(&missing).unwrap(), "H1\nA1");
+        }
+    }
+
+    #[test]
+    fn test_pipe() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "id,name\n1,ann\n2,bob lee\n3,";
+
+        let pipe = |batch: bool, allow_exec: bool| Cli {
+            command: Commands::Pipe(PipeConfig {
+                path: test_dir.to_string(),
+                column: "name".to_string(),
+                command: "tr a-z A-Z".to_string(),
+                batch,
+                allow_exec,
+                common: CommonConfig::default(),
+            }),
+        };
+        fs::write(&path, content).unwrap();
+        assert!(run(pipe(false, false)).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+        for batch in [false, true] {
+            fs::write(&path, content).unwrap();
+            run(pipe(batch, true)).unwrap();
+            assert_eq!(
+                fs::read_to_string(&path).unwrap(),
+                "id,name\n1,ANN\n2,BOB LEE\n3,\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_distinct_strict() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        fs::write(format!("{}/a.csv", test_dir), "status\nopen").unwrap();
+        fs::write(format!("{}/b.csv", test_dir), "id\n1").unwrap();
+
+        for strict in [false, true] {
+            let cli = Cli {
+                command: Commands::Distinct(DistinctConfig {
+                    path: test_dir.to_string(),
+                    column: Some("status".to_string()),
+                    top: None,
+                    dump_distinct: None,
+                    sort: DistinctSort::Count,
+                    preserve_order: false,
+                    common: CommonConfig {
+                        strict,
+                        ..CommonConfig::default()
+                    },
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), !strict);
+        }
+    }
+
+    #[test]
+    fn test_from_json() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let output = temp_dir();
+        let output_dir = output.path().to_str().unwrap();
+        fs::create_dir_all(format!("{}/nested", test_dir)).unwrap();
+        fs::write(
+            format!("{}/nested/events.jsonl", test_dir),
+            "{\"id\":1,\"name\":\"Ann, Lee\",\"tags\":[\"a\"]}\n\n{\"name\":\"Bob\",\"id\":2,\"ok\":true}\n{\"id\":3,\"name\":null}\n",
+        )
+        .unwrap();
+
+        let from_json_test_cases = [
+            (
+                None,
+                "id,name,tags\n1,\"Ann, Lee\",\"[\"\"a\"\"]\"\n2,Bob,\n3,,\n",
+            ),
+            (Some(vec!["ok", "id"]), "ok,id\n,1\ntrue,2\n,3\n"),
+        ];
+        for (columns, expected) in from_json_test_cases {
+            let cli = Cli {
+                command: Commands::FromJson(FromJsonConfig {
+                    path: test_dir.to_string(),
+                    output_dir: output_dir.to_string(),
+                    columns: columns.map(|c| c.iter().map(|c| c.to_string()).collect()),
+                    extensions: vec!["jsonl".to_string()],
+                    delimiter: None,
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(
+                fs::read_to_string(format!("{}/nested/events.csv", output_dir)).unwrap(),
+                expected
+            );
+        }
+
+        fs::write(format!("{}/nested/events.jsonl", test_dir), "[1, 2]\n").unwrap();
+        let cli = Cli {
+            command: Commands::FromJson(FromJsonConfig {
+                path: test_dir.to_string(),
+                output_dir: output_dir.to_string(),
+                columns: None,
+                extensions: vec!["jsonl".to_string()],
+                delimiter: None,
+            }),
+        };
+        assert!(run(cli).is_err());
+    }
+
+    #[test]
+    fn test_to_fixed() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let output = temp_dir();
+        let output_dir = output.path().to_str().unwrap();
+        fs::write(
+            format!("{}/test.csv", test_dir),
+            "id,name\n1,Ann\n22,Bartholomew",
+        )
+        .unwrap();
+        let output = format!("{}/test.txt", output_dir);
+
+        let to_fixed = |align: FixedAlign, truncate: bool| Cli {
+            command: Commands::ToFixed(ToFixedConfig {
+                path: test_dir.to_string(),
+                widths: vec![3, 6],
+                output_dir: output_dir.to_string(),
+                align,
+                truncate,
+                common: CommonConfig::default(),
+            }),
+        };
+        assert!(run(to_fixed(FixedAlign::Left, false)).is_err());
+        run(to_fixed(FixedAlign::Left, true)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "1  Ann   \n22 Bartho\n"
+        );
+        run(to_fixed(FixedAlign::Right, true)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "  1   Ann\n 22Bartho\n"
+        );
+    }
+
+    #[test]
+    fn test_verify_after() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "H1,H2,H3\nA1,A2,A3\nB1,B2,B3";
+
+        let migration = ReorderMigration::new(ReorderConfig {
+            path: test_dir.to_string(),
+            column: "H3".to_string(),
+            occurrence: 1,
+            order: 1,
+            only_header: false,
+            verify_after: true,
+            diff_columns: false,
+            record_permutation: None,
+            common: CommonConfig::default(),
+        });
+        fs::write(&path, "H3,H1,H2\nA3,A1,A2\nB3,B1,B2\n").unwrap();
+        let verify = |check_row: fn(&StringRecord, &StringRecord) -> bool| {
+            migration.verify_written(
+                &PathBuf::from(&path),
+                content.as_bytes(),
+                content,
+                &migration.config.common,
+                (0, "H3"),
+                check_row,
+            )
+        };
+        verify(|o, w| o.len() == w.len()).unwrap();
+        let err = verify(|o, w| o == w).unwrap_err().to_string();
+        assert!(err.contains("row 1"), "{}", err);
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+        for only_header in [false, true] {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Reorder(ReorderConfig {
+                    path: test_dir.to_string(),
+                    column: "H3".to_string(),
+                    occurrence: 1,
+                    order: 1,
+                    only_header,
+                    verify_after: true,
+                    diff_columns: false,
+                    record_permutation: None,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+        }
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "H3,H1,H2\nA1,A2,A3\nB1,B2,B3"
+        );
+
+        let cli = Cli {
+            command: Commands::Insert(InsertConfig {
+                path: test_dir.to_string(),
+                column: "H_new".to_string(),
+                default_value: Some("V_new".to_string()),
+                template: None,
+                default_env: None,
+                default_builtin: None,
+                order: InsertOrder::Position(2),
+                only_header: true,
+                verify_after: true,
+                diff_columns: false,
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "H3,H_new,H1,H2\nA1,A2,A3\nB1,B2,B3"
+        );
+    }
+
+    #[test]
+    fn test_transpose() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/config.csv", test_dir);
+        let output = format!("{}/transposed.csv", test_dir);
+        fs::write(&path, "key,a,b\nhost,x,y\nport,1\n").unwrap();
+
+        let transpose = |path: &str| Cli {
+            command: Commands::Transpose(TransposeConfig {
+                path: path.to_string(),
+                output: output.clone(),
+            }),
+        };
+        run(transpose(&path)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "key,host,port\na,x,1\nb,y,\n"
+        );
+        assert!(run(transpose(test_dir)).is_err());
+    }
+
+    #[test]
+    fn test_melt() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        fs::write(&path, "id,name,q1,q2\n1,Ann,10,20\n2,Bob,30,").unwrap();
+
+        let cli = Cli {
+            command: Commands::Melt(MeltConfig {
+                path: test_dir.to_string(),
+                id_columns: vec!["id".to_string()],
+                value_columns: vec!["q1".to_string(), "q2".to_string()],
+                columns_file: None,
+                var_name: "quarter".to_string(),
+                value_name: "sales".to_string(),
+                output: OutputConfig::default(),
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "id,quarter,sales\n1,q1,10\n1,q2,20\n2,q1,30\n2,q2,\n"
+        );
+    }
+
+    #[test]
+    fn test_pivot() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "id,quarter,sales\n1,q1,10\n1,q2,20\n2,q2,30\n1,q1,11";
+
+        let pivot_test_cases = [
+            (PivotAggregate::First, Some("id,q1,q2\n1,10,20\n2,,30\n")),
+            (PivotAggregate::Last, Some("id,q1,q2\n1,11,20\n2,,30\n")),
+            (PivotAggregate::Error, None),
+        ];
+        for (aggregate, expected) in pivot_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Pivot(PivotConfig {
+                    path: test_dir.to_string(),
+                    index: vec!["id".to_string()],
+                    columns_file: None,
+                    column: "quarter".to_string(),
+                    value: "sales".to_string(),
+                    aggregate,
+                    output: OutputConfig::default(),
+                    common: CommonConfig::default(),
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), expected.is_some());
+            let expected = expected.unwrap_or(content);
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_columns_file() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let columns_file = format!("{}/columns.txt", test_dir);
+        fs::write(&path, "name,work,mobile,home\nAnn,,,3\nBob,,2,3").unwrap();
+        fs::write(&columns_file, "# fallbacks\nmobile\n\n  home\n").unwrap();
+
+        let cli = Cli {
+            command: Commands::Coalesce(CoalesceConfig {
+                path: test_dir.to_string(),
+                into: "phone".to_string(),
+                from: vec!["work".to_string()],
+                columns_file: Some(columns_file.clone()),
+                order: InsertOrder::Last,
+                keep_sources: false,
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "name,phone\nAnn,3\nBob,2\n"
+        );
+
+        fs::write(&columns_file, "# nothing yet\n").unwrap();
+        let mut columns = vec![];
+        assert!(merge_columns_file(&mut columns, &Some(columns_file)).is_err());
+    }
+
+    #[test]
+    fn test_replace_headers() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "First Name,Last  Name,id\r\nA B,C D,1\r\n";
+
+        let replace_headers_test_cases = [
+            (" ", "_", false, "First_Name,Last__Name,id\r\nA B,C D,1\r\n"),
+            (
+                r"\s+",
+                "_",
+                true,
+                "First_Name,Last_Name,id\r\nA B,C D,1\r\n",
+            ),
+            (
+                r"^(\w+) (\w+)$",
+                "${2}_$1",
+                true,
+                "Name_First,Last  Name,id\r\nA B,C D,1\r\n",
+            ),
+            ("x", "y", false, content),
+        ];
+        for (find, replace, regex, expected) in replace_headers_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::ReplaceHeaders(ReplaceHeadersConfig {
+                    path: test_dir.to_string(),
+                    find: find.to_string(),
+                    replace: replace.to_string(),
+                    regex,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_dedupe_columns() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "id,email,email,mail,name\n1,a@x,a@x,a@x,A\n2,b@x,b@x,b@x,B\n";
+
+        let dedupe_columns_test_cases = [
+            (false, "id,email,name\n1,a@x,A\n2,b@x,B\n"),
+            (true, "id,email,mail,name\n1,a@x,a@x,A\n2,b@x,b@x,B\n"),
+        ];
+        for (by_name, expected) in dedupe_columns_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::DedupeColumns(DedupeColumnsConfig {
+                    path: test_dir.to_string(),
+                    by_name,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+
+        // a column differing in a single row is kept, and a file with only
+        // a header has nothing to compare
+        for content in ["a,b\n1,1\n2,3\n", "a,b,c\n", "a,a\n"] {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::DedupeColumns(DedupeColumnsConfig {
+                    path: test_dir.to_string(),
+                    by_name: false,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        }
+
+        // --sample keeps only the first rows even with nothing to drop
+        fs::write(&path, "a,b\n1,2\n3,4\n").unwrap();
+        let cli = Cli {
+            command: Commands::DedupeColumns(DedupeColumnsConfig {
+                path: test_dir.to_string(),
+                by_name: false,
+                common: CommonConfig {
+                    sample: Some(1),
+                    ..CommonConfig::default()
+                },
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a,b\n1,2\n");
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let state = temp_dir();
+        let checkpoint = format!("{}/checkpoint", state.path().to_str().unwrap());
+        let a = format!("{}/a.csv", test_dir);
+        let b = format!("{}/b.csv", test_dir);
+        let empty = format!("{}/empty.csv", test_dir);
+        fs::write(&a, "H1\nA1").unwrap();
+        fs::write(&b, "H1\nB1").unwrap();
+        fs::write(&empty, "").unwrap();
+        // a.csv was finished by an earlier, interrupted run
+        fs::write(&checkpoint, format!("{}\n", a)).unwrap();
+
+        let cli = Cli {
+            command: Commands::Insert(InsertConfig {
+                path: test_dir.to_string(),
+                column: "H0".to_string(),
+                default_value: Some("0".to_string()),
+                template: None,
+                default_env: None,
+                default_builtin: None,
+                order: InsertOrder::Position(1),
+                only_header: false,
+                verify_after: false,
+                diff_columns: false,
+                common: CommonConfig {
+                    checkpoint: Some(checkpoint.clone()),
+                    ..CommonConfig::default()
+                },
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "H1\nA1");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "H0,H1\n0,B1\n");
+        // the skipped empty file is left for the next run
+        assert_eq!(
+            fs::read_to_string(&checkpoint).unwrap(),
+            format!("{}\n{}\n", a, b)
+        );
+        assert_eq!(fs::read_dir(test_dir).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn test_normalize_headers() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+
+        let normalize_headers_test_cases = [
+            (
+                " First   Name ,Email,id\nA  B, a@x ,1\n",
+                false,
+                "First Name,Email,id\nA  B, a@x ,1\n",
+            ),
+            (
+                "Email,email ,EMAIL\na,b,c\n",
+                true,
+                "email,email_2,email_3\na,b,c\n",
+            ),
+            ("email,Email\na,b\n", false, "email,Email\na,b\n"),
+            ("a,a,a_2\n1,2,3\n", false, "a,a_2,a_2_2\n1,2,3\n"),
+        ];
+        for (content, lower, expected) in normalize_headers_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::NormalizeHeaders(NormalizeConfig {
+                    path: test_dir.to_string(),
+                    lower,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_backslash_escape() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        fs::write(&path, "H1,H2\nA1,\"say \\\"hi\\\", then go\"\n").unwrap();
+
+        let cli = Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H2".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: false,
+                verify_after: true,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig {
+                    escape: Some(b'\\'),
+                    no_double_quote: true,
+                    ..CommonConfig::default()
+                },
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "H2,H1\n\"say \\\"hi\\\", then go\",A1\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_columns() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "H1,H2,H3\nA1,A2,A3\n";
+        fs::write(&path, content).unwrap();
+
+        let migration = ReorderMigration::new(ReorderConfig {
+            path: test_dir.to_string(),
+            column: "H3".to_string(),
+            occurrence: 1,
+            order: 1,
+            only_header: false,
+            verify_after: false,
+            diff_columns: true,
+            record_permutation: None,
+            common: CommonConfig::default(),
+        });
+        migration.run().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+        let before = StringRecord::from(vec!["H1", "H2", "H3"]);
+        let column_diff_test_cases: [(&[&str], bool); 4] = [
+            (&["H3", "H1", "H2"], true),
+            (&["H1", "H_new", "H2", "H3"], true),
+            (&["H2", "H1"], true),
+            (&["H1", "H2", "H3"], false),
+        ];
+        for (after, changed) in column_diff_test_cases {
+            assert_eq!(migration.report_column_diff(&before, after), changed);
+        }
+    }
+
+    #[test]
+    fn test_assert() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let content = "id,email\n1,a@x.io\n2,\n3,not-an-email\n";
+        fs::write(format!("{}/a.csv", test_dir), content).unwrap();
+        fs::write(format!("{}/b.csv", test_dir), "id,email\n4,b@y.io\n").unwrap();
+
+        let assert_test_cases = [
+            (r"^[^@\s]+@[^@\s]+$", false, Some(2)),
+            (r"^[^@\s]+@[^@\s]+$", true, Some(1)),
+            (r"^([^@\s]+@[^@\s]+|not-an-email)$", true, None),
+        ];
+        for (pattern, allow_empty, failures) in assert_test_cases {
+            let migration = AssertMigration::new(AssertConfig {
+                path: test_dir.to_string(),
+                column: "email".to_string(),
+                pattern: pattern.to_string(),
+                allow_empty,
+                common: CommonConfig::default(),
+            });
+            let files = migration.iter_csv_files(test_dir, &migration.config.common);
+            let counted = migration.check_values(files, &Regex::new(pattern).unwrap());
+            assert_eq!(counted.unwrap(), failures.unwrap_or_default());
+            assert_eq!(migration.run().is_ok(), failures.is_none());
+        }
+        assert_eq!(
+            fs::read_to_string(format!("{}/a.csv", test_dir)).unwrap(),
+            content
+        );
+    }
+
+    #[test]
+    fn test_output_suffix() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/data.csv", test_dir);
+        let suffixed = format!("{}/data.migrated.csv", test_dir);
+        let content = "H1\nA1\nB1";
+        fs::write(&path, content).unwrap();
+
+        let head = |force| {
+            HeadMigration::new(HeadConfig {
+                path: path.clone(),
+                rows: 1,
+                output: OutputConfig {
+                    output_dir: None,
+                    output_suffix: Some(".migrated".to_string()),
+                    force,
+                    rename_to: None,
+                },
+                common: CommonConfig::default(),
+            })
+            .run()
+        };
+        head(false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        assert_eq!(fs::read_to_string(&suffixed).unwrap(), "H1\nA1\n");
+
+        fs::write(&suffixed, "stale").unwrap();
+        assert!(head(false).is_err());
+        assert_eq!(fs::read_to_string(&suffixed).unwrap(), "stale");
+        head(true).unwrap();
+        assert_eq!(fs::read_to_string(&suffixed).unwrap(), "H1\nA1\n");
+    }
+
+    #[test]
+    fn test_clamp() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "id,temp\n1,-40.5\n2,21.3\n3,150\n4,n/a\n5,";
+
+        let clamp_test_cases = [
+            (
+                Some(-20.0),
+                Some(60.0),
+                ClampOnError::Keep,
+                Some("id,temp\n1,-20\n2,21.3\n3,60\n4,n/a\n5,\n"),
+            ),
+            (
+                None,
+                Some(100.0),
+                ClampOnError::Clear,
+                Some("id,temp\n1,-40.5\n2,21.3\n3,100\n4,\n5,\n"),
+            ),
+            (Some(0.0), None, ClampOnError::Error, None),
+        ];
+        for (min, max, on_error, expected) in clamp_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Clamp(ClampConfig {
+                    path: test_dir.to_string(),
+                    column: ColumnSelector {
+                        column: Some("temp".to_string()),
+                        column_regex: None,
+                    },
+                    min,
+                    max,
+                    on_error,
+                    dry_run: false,
+                    row_filter: None,
+                    common: CommonConfig::default(),
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), expected.is_some());
+            let expected = expected.unwrap_or(content);
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_buffer_size() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        // quoted fields spanning the buffer boundary
+        let long = "x".repeat(3000);
+        fs::write(
+            &path,
+            format!("H1,H2\n\"A,{}\",A2\nB1,\"B\"\"{}\"\n", long, long),
+        )
+        .unwrap();
+
+        let cli = Cli {
+            command: Commands::Reorder(ReorderConfig {
+                path: test_dir.to_string(),
+                column: "H2".to_string(),
+                occurrence: 1,
+                order: 1,
+                only_header: false,
+                verify_after: true,
+                diff_columns: false,
+                record_permutation: None,
+                common: CommonConfig {
+                    buffer_size: Some(1024),
+                    ..CommonConfig::default()
+                },
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("H2,H1\nA2,\"A,{}\"\n\"B\"\"{}\",B1\n", long, long)
+        );
+    }
+
+    #[test]
+    fn test_rename_to() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        fs::create_dir_all(format!("{}/jan", test_dir)).unwrap();
+        fs::create_dir_all(format!("{}/feb", test_dir)).unwrap();
+        fs::write(format!("{}/jan/sales.csv", test_dir), "H1\nA1\nB1").unwrap();
+        fs::write(format!("{}/feb/sales.csv", test_dir), "H1\nC1").unwrap();
+
+        let head = |rename_to: &str| {
+            HeadMigration::new(HeadConfig {
+                path: test_dir.to_string(),
+                rows: 1,
+                output: OutputConfig {
+                    rename_to: Some(rename_to.to_string()),
+                    ..OutputConfig::default()
+                },
+                common: CommonConfig::default(),
+            })
+            .run()
+        };
+        assert!(head("{parent}/{stem}.{ext}").is_err());
+        assert!(head("{stem}.{extension}").is_err());
+        head("{parent}_{stem}.{ext}").unwrap();
+        assert_eq!(
+            fs::read_to_string(format!("{}/jan/jan_sales.csv", test_dir)).unwrap(),
+            "H1\nA1\n"
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{}/feb/feb_sales.csv", test_dir)).unwrap(),
+            "H1\nC1\n"
+        );
+        assert!(!Path::new(&format!("{}/jan/sales.csv", test_dir)).exists());
+
+        // two files in one directory can't both take a name without {stem}
+        fs::write(format!("{}/feb/returns.csv", test_dir), "H1\nD1\nE1").unwrap();
+        assert!(head("{parent}.{ext}").is_err());
+        assert_eq!(
+            fs::read_to_string(format!("{}/feb/returns.csv", test_dir)).unwrap(),
+            "H1\nD1\nE1"
+        );
+    }
+
+    #[test]
+    fn test_self_check() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+
+        let self_check_test_cases = [
+            ("H1,H2\nA1,A2\n", None),
+            (
+                "H1,H2\n\"A,1\",\"A\"\"2\"\n\"\"\"B\",\"multi\nline\"\n",
+                None,
+            ),
+            ("H1,H2\nA1,A2", Some("a trailing newline is added")),
+            ("H1,H2\r\nA1,A2\r\n", Some("CR line terminators become LF")),
+            (
+                "\u{feff}H1,H2\nA1,A2\n",
+                Some("the byte order mark is dropped"),
+            ),
+            (
+                "H1,H2\n\"A1\",A2\n",
+                Some("quoting or fields differ from line 2"),
+            ),
+            (
+                "H1,H2\nA1,\" A2\"\n",
+                Some("quoting or fields differ from line 2"),
+            ),
+        ];
+        for (content, difference) in self_check_test_cases {
+            fs::write(&path, content).unwrap();
+            let migration = SelfCheckMigration::new(SelfCheckConfig {
+                path: test_dir.to_string(),
+                common: CommonConfig::default(),
+            });
+            let found = migration.round_trip(Path::new(&path)).unwrap();
+            assert_eq!(found.as_deref(), difference, "{:?}", content);
+            assert_eq!(migration.run().is_ok(), difference.is_none());
+        }
+    }
+
+    #[test]
+    fn test_range() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "H1\nA1\nB1\nC1\nD1";
+
+        let range_test_cases = [
+            ("2:3", Some("H1\nB1\nC1\n")),
+            ("3:100", Some("H1\nC1\nD1\n")),
+            ("9:10", Some("H1\n")),
+            ("0:2", None),
+            ("3:2", None),
+            ("3", None),
+        ];
+        for (rows_range, expected) in range_test_cases {
+            let parsed = rows_range.parse::<RowsRange>();
+            assert_eq!(parsed.is_ok(), expected.is_some(), "{}", rows_range);
+            let (Ok(rows_range), Some(expected)) = (parsed, expected) else {
+                continue;
+            };
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Range(RangeConfig {
+                    path: test_dir.to_string(),
+                    rows_range,
+                    output: OutputConfig::default(),
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_retries() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        fs::write(&path, "H1\nA1").unwrap();
+        let migration = HeadMigration::new(HeadConfig {
+            path: test_dir.to_string(),
+            rows: 1,
+            output: OutputConfig::default(),
+            common: CommonConfig {
+                retries: 2,
+                retry_delay: 1,
+                ..CommonConfig::default()
+            },
+        });
+        let files = [PathBuf::from(&path)];
+
+        // failures before succeeding, attempts made, whether the file succeeds
+        let retry_test_cases = [
+            (io::ErrorKind::Interrupted, 1, 2, true),
+            (io::ErrorKind::TimedOut, 5, 3, false),
+            (io::ErrorKind::NotFound, 1, 1, false),
+            (io::ErrorKind::InvalidInput, 1, 1, false),
+        ];
+        for (kind, failures, expected_attempts, succeeds) in retry_test_cases {
+            let mut attempts = 0;
+            let result = migration.migrate_files(&files, &migration.config.common, |_| {
+                attempts += 1;
+                if attempts <= failures {
+                    return Err(io::Error::from(kind).into());
+                }
+                Ok(true)
+            });
+            assert_eq!(result.is_ok(), succeeds, "{:?}", kind);
+            assert_eq!(attempts, expected_attempts, "{:?}", kind);
+        }
+
+        let wrapped: Result<(), _> = Err(io::Error::from(io::ErrorKind::TimedOut));
+        assert!(is_transient(wrapped.with_path(&path).unwrap_err().as_ref()));
+        let logic_error: Box<dyn Error> = "Column H9 not found".into();
+        assert!(!is_transient(logic_error.as_ref()));
+    }
+
+    #[test]
+    fn test_only_if_header() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let users = format!("{}/users.csv", test_dir);
+        let swapped = format!("{}/swapped.csv", test_dir);
+        let orders = format!("{}/orders.csv", test_dir);
+
+        let only_if_header_test_cases = [
+            (false, "H1,id,name\nV,1,Ann\n", "name,id\nBob,2"),
+            (true, "H1,id,name\nV,1,Ann\n", "H1,name,id\nV,Bob,2\n"),
+        ];
+        for (any_header_order, expected_users, expected_swapped) in only_if_header_test_cases {
+            fs::write(&users, "id,name\n1,Ann").unwrap();
+            fs::write(&swapped, "name,id\nBob,2").unwrap();
+            fs::write(&orders, "id,name,total\n1,Ann,9").unwrap();
+            let cli = Cli {
+                command: Commands::Insert(InsertConfig {
+                    path: test_dir.to_string(),
+                    column: "H1".to_string(),
+                    default_value: Some("V".to_string()),
+                    template: None,
+                    default_env: None,
+                    default_builtin: None,
+                    order: InsertOrder::Position(1),
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    common: CommonConfig {
+                        only_if_header: vec!["id".to_string(), "name".to_string()],
+                        any_header_order,
+                        ..CommonConfig::default()
+                    },
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&users).unwrap(), expected_users);
+            assert_eq!(fs::read_to_string(&swapped).unwrap(), expected_swapped);
+            assert_eq!(
+                fs::read_to_string(&orders).unwrap(),
+                "id,name,total\n1,Ann,9"
+            );
+        }
+    }
+
+    #[test]
+    fn test_anonymize() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "id,name,phone\n1,Ann,555-0101\n2,Bob,555-0102\n3,Ann,\n4,Cid,555-0104\n";
+
+        let anonymize = |mode| {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Anonymize(AnonConfig {
+                    path: test_dir.to_string(),
+                    columns: vec!["name".to_string(), "phone".to_string()],
+                    mode,
+                    seed: 7,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            let output = fs::read_to_string(&path).unwrap();
+            let mut reader = csv::Reader::from_reader(output.as_bytes());
+            let rows: Vec<StringRecord> = reader.records().map(Result::unwrap).collect();
+            (output, rows)
+        };
+
+        let (faked, rows) = anonymize(AnonMode::Fake);
+        assert_eq!(faked, anonymize(AnonMode::Fake).0);
+        assert!(!faked.contains("Ann") && !faked.contains("555"));
+        assert_eq!(rows[0][1], rows[2][1]);
+        assert_ne!(rows[0][1], rows[1][1]);
+        assert_eq!(&rows[2][2], "");
+        for (row, id) in rows.iter().zip(["1", "2", "3", "4"]) {
+            assert_eq!(&row[0], id);
+            assert!(row[1].len() == 3 && row[1].starts_with(|c: char| c.is_ascii_uppercase()));
+        }
+        assert!(Regex::new(r"^\d{3}-\d{4}$").unwrap().is_match(&rows[1][2]));
+
+        let (shuffled, rows) = anonymize(AnonMode::Shuffle);
+        assert_eq!(shuffled, anonymize(AnonMode::Shuffle).0);
+        let mut names: Vec<&str> = rows.iter().map(|row| &row[1]).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["Ann", "Ann", "Bob", "Cid"]);
+    }
+
+    #[test]
+    fn test_deaccent() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "café,città\nCrème Brûlée,São Paulo\nstraße,Ørsted\n";
+
+        let deaccent_test_cases = [
+            (None, "cafe,citta\nCreme Brulee,Sao Paulo\nstraße,Ørsted\n"),
+            (
+                Some("città"),
+                "café,città\nCrème Brûlée,Sao Paulo\nstraße,Ørsted\n",
+            ),
+        ];
+        for (column, expected) in deaccent_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Deaccent(DeaccentConfig {
+                    path: test_dir.to_string(),
+                    column: column.map(String::from),
+                    dry_run: false,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_column_regex() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+
+        let column_regex_test_cases = [
+            (
+                r"^amount_",
+                Some("id,amount_net,amount_tax,note\n7,-5,100,-20\n"),
+            ),
+            (r"^total$", None),
+        ];
+        for (column_regex, expected) in column_regex_test_cases {
+            let content = "id,amount_net,amount_tax,note\n7,-12.5,250,-20\n";
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Clamp(ClampConfig {
+                    path: test_dir.to_string(),
+                    column: ColumnSelector {
+                        column: None,
+                        column_regex: Some(Regex::new(column_regex).unwrap()),
+                    },
+                    min: Some(-5.0),
+                    max: Some(100.0),
+                    on_error: ClampOnError::Keep,
+                    dry_run: false,
+                    row_filter: None,
+                    common: CommonConfig {
+                        on_missing_column: OnMissingColumn::Error,
+                        ..CommonConfig::default()
+                    },
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), expected.is_some());
+            let expected = expected.unwrap_or(content);
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_lossy() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+
+        // replacing invalid UTF-8 warns, which fails a strict run
+        for (lossy, strict) in [(false, false), (true, true), (true, false)] {
+            fs::write(&path, b"H1,H2\nA\xff1,A2\nB1,\xc3\x28B2").unwrap();
+            let cli = Cli {
+                command: Commands::Reorder(ReorderConfig {
+                    path: test_dir.to_string(),
+                    column: "H2".to_string(),
+                    occurrence: 1,
+                    order: 1,
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    record_permutation: None,
+                    common: CommonConfig {
+                        lossy,
+                        strict,
+                        ..CommonConfig::default()
+                    },
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), lossy && !strict);
+        }
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "H2,H1\nA2,A\u{fffd}1\n\u{fffd}(B2,B1\n"
+        );
+    }
+
+    #[test]
+    fn test_rename_many() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let maps = temp_dir();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "cust_id,cust_name,total\r\n1,\"Ann, A\",9\r\n";
+        let json_map = format!("{}/map.json", maps.path().display());
+        let csv_map = format!("{}/map.csv", maps.path().display());
+        fs::write(
+            &json_map,
+            r#"{"cust_id": "id", "cust_name": "name", "region": "area"}"#,
+        )
+        .unwrap();
+        fs::write(&csv_map, "old,new\ncust_id,id\ncust_name,name\n").unwrap();
+        let clashing_map = format!("{}/clash.csv", maps.path().display());
+        fs::write(&clashing_map, "old,new\ncust_id,total\n").unwrap();
+
+        let rename_many_test_cases = [
+            (&json_map, Some("id,name,total\r\n1,\"Ann, A\",9\r\n")),
+            (&csv_map, Some("id,name,total\r\n1,\"Ann, A\",9\r\n")),
+            (&clashing_map, None),
+        ];
+        for (map, expected) in rename_many_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::RenameMany(RenameManyConfig {
+                    path: test_dir.to_string(),
+                    map: map.clone(),
+                    common: CommonConfig::default(),
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), expected.is_some());
+            let expected = expected.unwrap_or(content);
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_round() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "id,price\n1,12.3400000001\n2,5\n3,-0.001\n4,n/a\n5,\n6,1e3\n7,inf";
+
+        let round_test_cases = [
+            (
+                2,
+                "id,price\n1,12.34\n2,5.00\n3,0.00\n4,n/a\n5,\n6,1000.00\n7,inf\n",
+            ),
+            (0, "id,price\n1,12\n2,5\n3,0\n4,n/a\n5,\n6,1000\n7,inf\n"),
+        ];
+        for (decimals, expected) in round_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Round(RoundConfig {
+                    path: test_dir.to_string(),
+                    column: ColumnSelector {
+                        column: Some("price".to_string()),
+                        column_regex: None,
+                    },
+                    decimals,
+                    dry_run: false,
+                    row_filter: None,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_dump_distinct() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let output = temp_dir();
+        let output_dir = output.path().to_str().unwrap();
+        fs::write(
+            format!("{}/a.csv", test_dir),
+            "Order Status,city,\nopen,Oslo,x\ndone,Rome,y\nopen,Oslo,z\n",
+        )
+        .unwrap();
+        fs::write(format!("{}/b.csv", test_dir), "city\nLima\nOslo\n").unwrap();
+
+        let dump_distinct_test_cases = [
+            (Some(2), "Order_Status.csv", "value,count\nopen,2\ndone,1\n"),
+            (Some(2), "city.csv", "value,count\nOslo,3\nLima,1\n"),
+            (Some(2), "column.csv", "value,count\nx,1\ny,1\n"),
+            (None, "column.csv", "value,count\nx,1\ny,1\nz,1\n"),
+        ];
+        for (top, file, expected) in dump_distinct_test_cases {
+            let cli = Cli {
+                command: Commands::Distinct(DistinctConfig {
+                    path: test_dir.to_string(),
+                    column: None,
+                    top,
+                    dump_distinct: Some(output_dir.to_string()),
+                    sort: DistinctSort::Count,
+                    preserve_order: false,
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+            let written = fs::read_to_string(format!("{}/{}", output_dir, file)).unwrap();
+            assert_eq!(written, expected, "{}", file);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let link = format!("{}/link.csv", test_dir);
+        fs::write(&path, "H1\nA1\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        symlink(&path, &link).unwrap();
+
+        // a handle opened before the write still sees the old file, so the
+        // original is never truncated and rewritten in place
+        let mut open = File::open(&path).unwrap();
+        write_atomic(&link, "H1\nB1\n").unwrap();
+        let mut before = String::new();
+        open.read_to_string(&mut before).unwrap();
+        assert_eq!(before, "H1\nA1\n");
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "H1\nB1\n");
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        assert_eq!(fs::read_dir(test_dir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_name_columns() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = ",name, ,\n0,Ann,x,y\n";
+
+        let name_columns_test_cases = [
+            (
+                vec![],
+                Some("col_"),
+                Some("col_1,name,col_3,col_4\n0,Ann,x,y\n"),
+            ),
+            (
+                vec!["index", "note"],
+                None,
+                Some("index,name,note,\n0,Ann,x,y\n"),
+            ),
+            (vec!["name"], None, None),
+            (vec!["id", "id"], None, None),
+        ];
+        for (names, prefix, expected) in name_columns_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::NameColumns(NameColumnsConfig {
+                    path: test_dir.to_string(),
+                    names: names.into_iter().map(String::from).collect(),
+                    prefix: prefix.map(String::from),
+                    common: CommonConfig::default(),
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), expected.is_some());
+            let expected = expected.unwrap_or(content);
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_unreorder() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let state = temp_dir();
+        let record = format!("{}/permutations", state.path().to_str().unwrap());
+        let path = format!("{}/test.csv", test_dir);
+        let content = "a,b,c\n1,2,3\n";
+        fs::write(&path, content).unwrap();
 
-    Ok(())
-}
+        for (column, order) in [("c", 1), ("b", 1)] {
+            let cli = Cli {
+                command: Commands::Reorder(ReorderConfig {
+                    path: test_dir.to_string(),
+                    column: column.to_string(),
+                    occurrence: 1,
+                    order,
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    record_permutation: Some(record.clone()),
+                    common: CommonConfig::default(),
+                }),
+            };
+            run(cli).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b,c,a\n2,3,1\n");
+        assert_eq!(fs::read_to_string(&record).unwrap().lines().count(), 2);
 
-#[derive(Clone)]
-struct InsertMigration {
-    config: InsertConfig,
-}
-impl Migration for InsertMigration {
-    type ConfigType = InsertConfig;
+        let unreorder = |path: String| Cli {
+            command: Commands::Unreorder(UnreorderConfig {
+                path,
+                permutations: record.clone(),
+                common: CommonConfig::default(),
+            }),
+        };
+        // the same directory spelled differently still finds the recorded files
+        run(unreorder(format!("{}/./", test_dir))).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        // the headers no longer match the recorded "after" state
+        assert!(run(unreorder(test_dir.to_string())).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
 
-    fn new(config: Self::ConfigType) -> Self {
-        Self { config }
+        let other = temp_dir();
+        let other_path = format!("{}/test.csv", other.path().display());
+        fs::write(&other_path, "b,c,a\n2,3,1\n").unwrap();
+        assert!(run(unreorder(other.path().display().to_string())).is_err());
+        assert_eq!(fs::read_to_string(&other_path).unwrap(), "b,c,a\n2,3,1\n");
     }
 
-    fn run(&self) -> Result<(), Box<dyn Error>> {
-        let InsertConfig {
-            path,
-            column,
-            default_value,
-            order,
-        } = &self.config;
-        println!(
-            "Inserting {} with default value {} as #{} in path {}",
-            &column.blue(),
-            &default_value.blue(),
-            &order.to_string().blue(),
-            &path.blue()
-        );
-        let files = self.get_csv_files(&path)?;
-        for file in files {
-            println!("Migrating {:?}", &file);
-            self.insert_column(&file, &column, &default_value, *order)?;
-        }
+    #[test]
+    fn test_split_bytes() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let output_dir = format!("{}/parts", test_dir);
+        fs::write(&path, "id,name\n1,ab\n2,cd\n3,ef\n").unwrap();
 
-        Ok(())
+        // the 8 byte header is repeated in every part
+        let split_bytes_test_cases = [
+            (18, Some(vec!["id,name\n1,ab\n2,cd\n", "id,name\n3,ef\n"])),
+            (
+                13,
+                Some(vec![
+                    "id,name\n1,ab\n",
+                    "id,name\n2,cd\n",
+                    "id,name\n3,ef\n",
+                ]),
+            ),
+            (12, None),
+        ];
+        for (max_bytes, expected) in split_bytes_test_cases {
+            let _ = fs::remove_dir_all(&output_dir);
+            let cli = Cli {
+                command: Commands::SplitBytes(SplitBytesConfig {
+                    path: path.clone(),
+                    max_bytes,
+                    output_dir: output_dir.clone(),
+                    common: CommonConfig::default(),
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), expected.is_some());
+            for (i, part) in expected.unwrap_or_default().iter().enumerate() {
+                let part_path = format!("{}/test_{}.csv", output_dir, i + 1);
+                assert_eq!(&fs::read_to_string(part_path).unwrap(), part);
+            }
+        }
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "id,name\n1,ab\n2,cd\n3,ef\n"
+        );
     }
-}
 
-impl InsertMigration {
-    fn insert_column(
-        &self,
-        path: &PathBuf,
-        column: &str,
-        default_value: &str,
-        order: i32,
-    ) -> Result<(), Box<dyn Error>> {
-        let mut content = String::new();
-        File::open(path)?.read_to_string(&mut content)?;
-        let mut reader = csv::Reader::from_reader(content.as_bytes());
-        let mut writer = csv::Writer::from_path(path)?;
+    #[test]
+    fn test_trim_columns() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
 
-        // set headers
-        let headers = reader.headers()?.clone();
-        let mut new_headers = StringRecord::new();
-        for (i, header) in headers.iter().enumerate() {
-            if i as i32 == (order - 1) {
-                new_headers.push_field(column);
-            }
-            new_headers.push_field(header);
+        let trim_columns_test_cases = [
+            ("a,b,,\n1,2,,\n", None, "a,b\n1,2\n"),
+            ("a,b,,\n1,2,x,\n", None, "a,b,\n1,2,x\n"),
+            ("a,,c\n1,,3\n", None, "a,,c\n1,,3\n"),
+            (",,\n,,\n", None, "\"\"\n\"\"\n"),
+            ("a,b,\n1,2,\n3,4,\n", Some(1), "a,b\n1,2\n"),
+            ("a,b\n1,2\n3,4\n", Some(1), "a,b\n1,2\n"),
+        ];
+        for (content, sample, expected) in trim_columns_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::TrimColumns(TrimColumnsConfig {
+                    path: test_dir.to_string(),
+                    common: CommonConfig {
+                        sample,
+                        ..CommonConfig::default()
+                    },
+                }),
+            };
+            run(cli).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
         }
-        writer.write_record(&new_headers)?;
+    }
 
-        // set values
-        for record in reader.records() {
-            let record = record?;
-            let mut new_record = StringRecord::new();
-            for (j, field) in record.iter().enumerate() {
-                if j as i32 == (order - 1) {
-                    new_record.push_field(default_value);
-                }
-                new_record.push_field(field);
-            }
-            writer.write_record(&new_record)?;
+    #[test]
+    fn test_precheck() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let a = format!("{}/a.csv", test_dir);
+        let b = format!("{}/b.csv", test_dir);
+
+        // b.csv lacks the column, so with --strict a.csv is left alone too
+        for (strict, expected) in [(true, "a,b\n1,2\n"), (false, "b,a\n2,1\n")] {
+            fs::write(&a, "a,b\n1,2\n").unwrap();
+            fs::write(&b, "c,d\n3,4\n").unwrap();
+            let cli = Cli {
+                command: Commands::Reorder(ReorderConfig {
+                    path: test_dir.to_string(),
+                    column: "b".to_string(),
+                    occurrence: 1,
+                    order: 1,
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    record_permutation: None,
+                    common: CommonConfig {
+                        precheck: true,
+                        strict,
+                        ..CommonConfig::default()
+                    },
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), !strict);
+            assert_eq!(fs::read_to_string(&a).unwrap(), expected);
+            assert_eq!(fs::read_to_string(&b).unwrap(), "c,d\n3,4\n");
         }
+    }
 
-        Ok(())
+    #[test]
+    fn test_delimiter_presets() {
+        let parse = |args: &[&str]| {
+            let base = ["csv-migrator", "trim-columns", "--path", "."];
+            Cli::try_parse_from(base.iter().chain(args)).map(|cli| match cli.command {
+                Commands::TrimColumns(config) => config.common.delimiter(Path::new("a.csv")),
+                _ => unreachable!(),
+            })
+        };
+        assert_eq!(parse(&[]).unwrap(), b',');
+        assert_eq!(parse(&["--tab"]).unwrap(), b'\t');
+        assert_eq!(parse(&["--semicolon"]).unwrap(), b';');
+        assert_eq!(parse(&["--pipe"]).unwrap(), b'|');
+        assert!(parse(&["--tab", "--delimiter", ";"]).is_err());
+        assert!(parse(&["--tab", "--pipe"]).is_err());
     }
-}
 
-struct ReorderMigration {
-    config: ReorderConfig,
-}
-impl Migration for ReorderMigration {
-    type ConfigType = ReorderConfig;
+    #[test]
+    fn test_fill_from() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let state = temp_dir();
+        let values_file = format!("{}/values", state.path().to_str().unwrap());
+        let a = format!("{}/a.csv", test_dir);
+        let b = format!("{}/b.csv", test_dir);
 
-    fn new(config: Self::ConfigType) -> Self {
-        Self { config }
+        let fill_from_test_cases = [
+            (
+                "id",
+                "1\n2\n3\n",
+                false,
+                Some(("name,id\nx,1\ny,2\n", "name,id\nz,3\n")),
+            ),
+            (
+                "name",
+                "p\nq\nr\ns\n",
+                false,
+                Some(("name\np\nq\n", "name\nr\n")),
+            ),
+            (
+                "id",
+                "1\n2\n",
+                false,
+                Some(("name,id\nx,1\ny,2\n", "name,id\nz,\n")),
+            ),
+            ("id", "1\n2\n", true, None),
+        ];
+        for (column, values, strict, expected) in fill_from_test_cases {
+            fs::write(&a, "name\nx\ny\n").unwrap();
+            fs::write(&b, "name\nz\n").unwrap();
+            fs::write(&values_file, values).unwrap();
+            let cli = Cli {
+                command: Commands::FillFrom(FillFromConfig {
+                    path: test_dir.to_string(),
+                    column: column.to_string(),
+                    values_file: values_file.clone(),
+                    order: InsertOrder::Last,
+                    common: CommonConfig {
+                        strict,
+                        ..CommonConfig::default()
+                    },
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), expected.is_some());
+            if let Some((expected_a, expected_b)) = expected {
+                assert_eq!(fs::read_to_string(&a).unwrap(), expected_a);
+                assert_eq!(fs::read_to_string(&b).unwrap(), expected_b);
+            }
+        }
     }
 
-    fn run(&self) -> Result<(), Box<dyn Error>> {
-        let ReorderConfig {
-            path,
-            column,
-            order,
-        } = &self.config;
-        println!(
-            "Reordering {} to #{} path {}",
-            &column.blue(),
-            &order.to_string().blue(),
-            &path.blue()
+    #[test]
+    fn test_progress_bytes() {
+        assert_eq!(
+            progress_bytes(3_000_000, 12_000_000, Duration::from_secs(2)),
+            "3.0 of 12.0 MB read (25%), 1.5 MB/s"
+        );
+        assert_eq!(
+            progress_bytes(0, 0, Duration::ZERO),
+            "0.0 of 0.0 MB read (100%), 0.0 MB/s"
         );
+    }
 
-        let files = self.get_csv_files(&path)?;
-        for file in files {
-            println!("Migrating {:?}", &file);
-            self.shift_column(&file, &column, *order)?;
+    #[test]
+    fn test_where() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "type,code\nspecial,7\nplain,7\nspecialist,7\n";
+
+        let where_test_cases = [
+            (
+                "type==special",
+                Some("type,code\nspecial,007\nplain,7\nspecialist,7\n"),
+            ),
+            (
+                "type!=special",
+                Some("type,code\nspecial,7\nplain,007\nspecialist,007\n"),
+            ),
+            (
+                "type=~^spec",
+                Some("type,code\nspecial,007\nplain,7\nspecialist,007\n"),
+            ),
+            ("kind==special", None),
+        ];
+        for (row_filter, expected) in where_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Pad(PadConfig {
+                    path: test_dir.to_string(),
+                    column: ColumnSelector {
+                        column: Some("code".to_string()),
+                        column_regex: None,
+                    },
+                    row_filter: Some(row_filter.parse().unwrap()),
+                    width: 3,
+                    char: '0',
+                    side: PadSide::Left,
+                    dry_run: false,
+                    common: CommonConfig::default(),
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), expected.is_some());
+            assert_eq!(
+                fs::read_to_string(&path).unwrap(),
+                expected.unwrap_or(content)
+            );
         }
-        Ok(())
+        assert!("code".parse::<RowFilter>().is_err());
+        assert!("==x".parse::<RowFilter>().is_err());
     }
-}
 
-impl ReorderMigration {
-    fn shift_column(
-        &self,
-        path: &PathBuf,
-        column: &String,
-        order: i32,
-    ) -> Result<(), Box<dyn Error>> {
-        let mut content = String::new();
-        File::open(path)?.read_to_string(&mut content)?;
-        let mut reader = csv::Reader::from_reader(content.as_bytes());
-        let mut writer = csv::Writer::from_path(path)?;
+    #[test]
+    fn test_reorder_occurrence() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let content = "id,x,name,x\n1,a,n,b\n";
 
-        // headers
-        let original_headers = reader.headers()?.clone();
-        let mut new_headers = StringRecord::new();
-        let target_header_index = original_headers
-            .iter()
-            .position(|h| h == column)
-            .expect("Column not found");
-        if target_header_index as i32 == order - 1 {
-            println!(
-                "{}",
-                format!("Column {} already on #{}", column, order).yellow()
+        let occurrence_test_cases = [
+            (1, Some("x,id,name,x\na,1,n,b\n")),
+            (2, Some("x,id,x,name\nb,1,a,n\n")),
+            (3, None),
+        ];
+        for (occurrence, expected) in occurrence_test_cases {
+            fs::write(&path, content).unwrap();
+            let cli = Cli {
+                command: Commands::Reorder(ReorderConfig {
+                    path: test_dir.to_string(),
+                    column: "x".to_string(),
+                    occurrence,
+                    order: 1,
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    record_permutation: None,
+                    common: CommonConfig::default(),
+                }),
+            };
+            assert_eq!(run(cli).is_ok(), expected.is_some());
+            assert_eq!(
+                fs::read_to_string(&path).unwrap(),
+                expected.unwrap_or(content)
             );
-            writer.write_record(&original_headers.clone())?;
-            for r in reader.records() {
-                writer.write_record(&r.unwrap())?;
-            }
-            return Ok(());
         }
+    }
 
-        let target_header = original_headers.get(target_header_index).unwrap();
-        let mut headers_vec: Vec<&str> = original_headers.iter().collect();
-        headers_vec.remove(target_header_index);
-        let headers: StringRecord = headers_vec.into();
-        for (i, header) in headers.iter().enumerate() {
-            if i as i32 == order - 1 {
-                new_headers.push_field(target_header);
-            }
-            new_headers.push_field(header);
-        }
-        writer.write_record(&new_headers)?;
+    #[test]
+    fn test_header_inventory() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let state = temp_dir();
+        let output = format!("{}/inventory.csv", state.path().to_str().unwrap());
+        fs::write(format!("{}/a.csv", test_dir), "id,name\n1,x\n").unwrap();
+        fs::write(format!("{}/b.csv", test_dir), "name,id,x,x\ny,2,3,4\n").unwrap();
 
-        // values
-        for original_record in reader.records() {
-            let original_record = original_record?;
-            let target_value = original_record
-                .get(target_header_index)
-                .expect("Value to migrate not found");
-            let mut record = original_record.iter().collect::<Vec<&str>>();
-            record.remove(target_header_index);
-            let mut new_record = StringRecord::new();
-            let record_iter = record.iter().enumerate();
-            for (j, value) in record_iter {
-                if j as i32 == (order - 1) {
-                    new_record.push_field(target_value);
-                    new_record.push_field(value);
-                } else {
-                    new_record.push_field(value);
-                }
-            }
-            writer.write_record(&new_record)?;
+        let cli = Cli {
+            command: Commands::HeaderInventory(HeaderInventoryConfig {
+                path: test_dir.to_string(),
+                output: output.clone(),
+                common: CommonConfig::default(),
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "header,files,min_position,max_position\nid,2,1,2\nname,2,1,2\nx,1,3,4\n"
+        );
+    }
+
+    #[test]
+    fn test_output_not_input() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let left = format!("{}/left.csv", test_dir);
+        let right = format!("{}/right.csv", test_dir);
+        fs::write(&left, "id,name\n1,Ann\n").unwrap();
+        fs::write(&right, "id,city\n1,Oslo\n").unwrap();
+
+        // the same file under another spelling is still refused
+        for output in [format!("{}/./right.csv", test_dir), left.clone()] {
+            let cli = Cli {
+                command: Commands::Join(JoinConfig {
+                    left: left.clone(),
+                    right: right.clone(),
+                    on: "id".to_string(),
+                    output,
+                    how: JoinHow::Inner,
+                    matching: MatchConfig::default(),
+                }),
+            };
+            assert!(run(cli).is_err());
         }
+        assert_eq!(fs::read_to_string(&left).unwrap(), "id,name\n1,Ann\n");
+        assert_eq!(fs::read_to_string(&right).unwrap(), "id,city\n1,Oslo\n");
+        assert!(check_output_not_input(&format!("{}/new.csv", test_dir), &[&left]).is_ok());
+    }
 
-        Ok(())
+    #[test]
+    fn test_checkpoint_after_dry_run() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let state = temp_dir();
+        let checkpoint = format!("{}/checkpoint", state.path().to_str().unwrap());
+        let path = format!("{}/test.csv", test_dir);
+        fs::write(&path, "H1\n\"a\nb\"\n").unwrap();
+
+        for dry_run in [true, false] {
+            let cli = Cli {
+                command: Commands::Flatten(FlattenConfig {
+                    path: test_dir.to_string(),
+                    column: None,
+                    replacement: " ".to_string(),
+                    dry_run,
+                    common: CommonConfig {
+                        checkpoint: Some(checkpoint.clone()),
+                        ..CommonConfig::default()
+                    },
+                }),
+            };
+            run(cli).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "H1\na b\n");
+        assert_eq!(
+            fs::read_to_string(&checkpoint).unwrap(),
+            format!("{}\n", path)
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::io::{Read, Write};
+    #[test]
+    fn test_diff_by_header_name() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let a = format!("{}/a.csv", test_dir);
+        let b = format!("{}/b.csv", test_dir);
+        fs::write(&a, "H1,H2,H3\nA1,A2,A3\n").unwrap();
 
-    use super::*;
-    use pretty_assertions::assert_eq;
+        // a dropped column is reported once, not as a change to every row
+        let diff_test_cases = [
+            ("H1,H2\nA1,A2\n", 1),
+            ("H2,H1,H3\nA2,A1,A3\n", 1),
+            ("H2,H1,H3\nA2,B1,A3\n", 2),
+        ];
+        for (b_content, expected) in diff_test_cases {
+            fs::write(&b, b_content).unwrap();
+            let cli = Cli {
+                command: Commands::Diff(DiffConfig {
+                    a: a.clone(),
+                    b: b.clone(),
+                    key: None,
+                    matching: MatchConfig::default(),
+                }),
+            };
+            let err = run(cli).unwrap_err().to_string();
+            assert_eq!(err, format!("{} differences found", expected));
+        }
+    }
 
     #[test]
-    fn test_insert_column() {
-        let test_dir = "test_files/insert";
-        fs::remove_dir_all(test_dir).unwrap();
-        fs::create_dir_all(test_dir).unwrap();
-        let mut path = PathBuf::new();
-        path.push(format!("{}/test.csv", test_dir));
-        let mut file = File::create(path.clone()).unwrap();
-        file.write_all(
-            b"H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB1,B2,B3,B4,B5,B6,B7,B8,B9",
-        )
-        .unwrap();
+    fn test_shuffle_columns_independently() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = format!("{}/test.csv", test_dir);
+        let mut content = "name,phone\n".to_string();
+        for i in 0..20 {
+            content += &format!("name{},phone{}\n", i, i);
+        }
+        fs::write(&path, &content).unwrap();
 
         let cli = Cli {
-            command: Commands::Insert(InsertConfig {
+            command: Commands::Anonymize(AnonConfig {
                 path: test_dir.to_string(),
-                column: "H_new".to_string(),
-                default_value: "V_new".to_string(),
-                order: 3,
+                columns: vec!["name".to_string(), "phone".to_string()],
+                mode: AnonMode::Shuffle,
+                seed: 7,
+                common: CommonConfig::default(),
             }),
         };
         run(cli).unwrap();
-        let mut modified_file = File::open(path.clone()).unwrap();
-        let mut modified_content = String::new();
-        modified_file.read_to_string(&mut modified_content).unwrap();
-        assert_eq!(
-            modified_content,
-            String::from(
-                "H1,H2,H_new,H3,H4,H5,H6,H7,H8,H9\nA1,A2,V_new,A3,A4,A5,A6,A7,A8,A9\nB1,B2,V_new,B3,B4,B5,B6,B7,B8,B9\n"
-            )
-        )
+        let output = fs::read_to_string(&path).unwrap();
+        let mut reader = csv::Reader::from_reader(output.as_bytes());
+        let kept_together = reader
+            .records()
+            .map(Result::unwrap)
+            .filter(|row| row[0][4..] == row[1][5..])
+            .count();
+        assert!(kept_together < 5, "{} rows kept together", kept_together);
     }
 
     #[test]
-    fn test_reorder_column() {
-        let reorder_test_cases = vec![
-            ("H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB1,B2,B3,B4,B5,B6,B7,B8,B9".to_string(), "H3,H1,H2,H4,H5,H6,H7,H8,H9\nA3,A1,A2,A4,A5,A6,A7,A8,A9\nB3,B1,B2,B4,B5,B6,B7,B8,B9\n".to_string(), "H3", 1),
-            ("H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB,B,B,B4,B5,B6,B7,B8,B9".to_string(), "H3,H1,H2,H4,H5,H6,H7,H8,H9\nA3,A1,A2,A4,A5,A6,A7,A8,A9\nB,B,B,B4,B5,B6,B7,B8,B9\n".to_string(), "H3", 1),
-            ("H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB1,B2,B3,B4,B5,B6,B7,B8,B9".to_string(), "H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB1,B2,B3,B4,B5,B6,B7,B8,B9\n".to_string(), "H1", 1),
-            ("H1,H2,H3,H4,H5,H6,H7,H8,H9\nA1,A2,A3,A4,A5,A6,A7,A8,A9\nB1,B2,B3,B4,B5,B6,B7,B8,B9".to_string(), "H2,H1,H3,H4,H5,H6,H7,H8,H9\nA2,A1,A3,A4,A5,A6,A7,A8,A9\nB2,B1,B3,B4,B5,B6,B7,B8,B9\n".to_string(), "H1", 2),
+    fn test_replace_headers_rejects_empty_find() {
+        let parse = |find: &str| {
+            Cli::try_parse_from([
+                "csv-migrator",
+                "replace-headers",
+                "--path",
+                ".",
+                "--find",
+                find,
+                "--replace",
+                "_",
+            ])
+        };
+        assert!(parse("").is_err());
+        assert!(parse(" ").is_ok());
+    }
 
-        ];
+    #[test]
+    fn test_verify_restores_raw_bytes() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let path = PathBuf::from(format!("{}/test.csv", test_dir));
+        let raw = b"H1,H2\r\nA\xff,B\r".to_vec();
+        let common = CommonConfig {
+            lossy: true,
+            cr_records: true,
+            ..CommonConfig::default()
+        };
+        let migration = ReorderMigration::new(ReorderConfig {
+            path: test_dir.to_string(),
+            column: "H2".to_string(),
+            occurrence: 1,
+            order: 1,
+            only_header: false,
+            verify_after: true,
+            diff_columns: false,
+            record_permutation: None,
+            common,
+        });
+        let content = migration.config.common.decode(&path, raw.clone()).unwrap();
+        fs::write(&path, "H2,H1\nB,A\n").unwrap();
+        let verified = migration.verify_written(
+            &path,
+            &raw,
+            &content,
+            &migration.config.common,
+            (0, "H2"),
+            |o, w| o == w,
+        );
+        assert!(verified.is_err());
+        assert_eq!(fs::read(&path).unwrap(), raw);
+    }
 
-        let test_dir = "test_files/reorder";
-        fs::remove_dir_all(test_dir).unwrap();
-        fs::create_dir_all(test_dir).unwrap();
-        for (i, tc) in reorder_test_cases.iter().enumerate() {
-            let (init, expected, column, order) = tc;
-            let mut path = PathBuf::new();
-            path.push(format!("{}/test_{}.csv", test_dir, i));
-            let mut file = File::create(path.clone()).unwrap();
-            let buff = init.clone().into_bytes();
-            file.write_all(&buff).unwrap();
+    #[test]
+    fn test_fill_from_resumed() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        let state = temp_dir();
+        let checkpoint = format!("{}/checkpoint", state.path().to_str().unwrap());
+        let values_file = format!("{}/values", state.path().to_str().unwrap());
+        let a = format!("{}/a.csv", test_dir);
+        let b = format!("{}/b.csv", test_dir);
+        // a.csv was filled with the first two values by an interrupted run
+        fs::write(&a, "name,id\nx,1\ny,2\n").unwrap();
+        fs::write(&b, "name\nz\n").unwrap();
+        fs::write(&values_file, "1\n2\n3\n").unwrap();
+        fs::write(&checkpoint, format!("{}\n", a)).unwrap();
+
+        let cli = Cli {
+            command: Commands::FillFrom(FillFromConfig {
+                path: test_dir.to_string(),
+                column: "id".to_string(),
+                values_file,
+                order: InsertOrder::Last,
+                common: CommonConfig {
+                    checkpoint: Some(checkpoint.clone()),
+                    strict: true,
+                    ..CommonConfig::default()
+                },
+            }),
+        };
+        run(cli).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "name,id\nx,1\ny,2\n");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "name,id\nz,3\n");
+    }
 
+    #[test]
+    fn test_summary_per_run() {
+        let dir = temp_dir();
+        let test_dir = dir.path().to_str().unwrap();
+        fs::write(format!("{}/test.csv", test_dir), "H1\nA1\n").unwrap();
+        for column in ["H0", "H2"] {
             let cli = Cli {
-                command: Commands::Reorder(ReorderConfig {
+                command: Commands::Insert(InsertConfig {
                     path: test_dir.to_string(),
                     column: column.to_string(),
-                    order: *order,
+                    default_value: Some("0".to_string()),
+                    template: None,
+                    default_env: None,
+                    default_builtin: None,
+                    order: InsertOrder::Last,
+                    only_header: false,
+                    verify_after: false,
+                    diff_columns: false,
+                    common: CommonConfig::default(),
                 }),
             };
             run(cli).unwrap();
-            let mut modified_file = File::open(path.clone()).unwrap();
-            let mut modified_content = String::new();
-            modified_file.read_to_string(&mut modified_content).unwrap();
-            assert_eq!(modified_content, *expected)
+            assert!(summary().contains("1 migrated"), "{}", summary());
         }
     }
 }